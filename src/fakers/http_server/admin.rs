@@ -0,0 +1,93 @@
+use std::sync::Arc;
+
+use serde_json::{json, Value as jsonValue};
+
+use axum::Router;
+use axum::routing::{delete, get, post};
+use axum::extract::{Extension, Json, Path};
+use axum::http::StatusCode;
+
+use log::*;
+
+use crate::utils::template;
+use super::journal::JournalEntry;
+use super::server::{Route, ServerState, Stubs};
+
+/// Admin API of the server, served on its own address so it never shadows the stubbed routes:
+///
+/// - `GET /__admin/routes` lists the routes with their ids, `POST` adds a route tried after the others
+/// - `DELETE /__admin/routes/:id` removes a route
+/// - `GET /__admin/requests` returns the journal as a JSON array, `DELETE` clears it
+/// - `POST /__admin/counters/reset` resets the call counters of the sequences & the template counters
+/// - `POST /__admin/reset` restores the routes of the configuration and resets the counters & the journal
+pub fn router(state: Arc<ServerState>) -> Router {
+    Router::new()
+        .route("/__admin/routes", get(list_routes).post(add_route))
+        .route("/__admin/routes/:id", delete(remove_route))
+        .route("/__admin/requests", get(list_requests).delete(clear_requests))
+        .route("/__admin/counters/reset", post(reset_counters))
+        .route("/__admin/reset", post(reset))
+        .layer(Extension(state))
+}
+
+async fn list_routes(Extension(state): Extension<Arc<ServerState>>) -> Json<jsonValue> {
+    let routes: Vec<jsonValue> = state.stubs.read().unwrap().routes.iter()
+        .map(|(id, r)| {
+            let mut v = serde_json::to_value(r).unwrap_or(jsonValue::Null);
+            v["id"] = json!(id);
+            v
+        })
+        .collect();
+
+    Json(jsonValue::Array(routes))
+}
+
+async fn add_route(Extension(state): Extension<Arc<ServerState>>, Json(route): Json<Route>) -> (StatusCode, Json<jsonValue>) {
+    if let Err(e) = route.validate() {
+        return (StatusCode::BAD_REQUEST, Json(json!({"error": e.to_string()})));
+    }
+
+    info!("http-server: adding the route {} {}", route.method, route.path);
+    let id = state.stubs.write().unwrap().add(route);
+
+    (StatusCode::CREATED, Json(json!({"id": id})))
+}
+
+async fn remove_route(Extension(state): Extension<Arc<ServerState>>, Path(id): Path<usize>) -> (StatusCode, Json<jsonValue>) {
+    if !state.stubs.write().unwrap().remove(id) {
+        return (StatusCode::NOT_FOUND, Json(json!({"error": format!("route {} not found", id)})));
+    }
+
+    info!("http-server: route {} removed", id);
+    state.calls.lock().unwrap().retain(|(i, _), _| *i != id);
+
+    (StatusCode::OK, Json(json!({"id": id})))
+}
+
+async fn list_requests(Extension(state): Extension<Arc<ServerState>>) -> Json<Vec<JournalEntry>> {
+    Json(state.journal.entries())
+}
+
+async fn clear_requests(Extension(state): Extension<Arc<ServerState>>) -> StatusCode {
+    state.journal.clear();
+
+    StatusCode::NO_CONTENT
+}
+
+async fn reset_counters(Extension(state): Extension<Arc<ServerState>>) -> StatusCode {
+    state.calls.lock().unwrap().clear();
+    template::reset_counters(&state.host_addr);
+
+    StatusCode::NO_CONTENT
+}
+
+async fn reset(Extension(state): Extension<Arc<ServerState>>) -> StatusCode {
+    info!("http-server: resetting the routes, the counters & the journal");
+
+    *state.stubs.write().unwrap() = Stubs::new(&state.initial_routes);
+    state.calls.lock().unwrap().clear();
+    template::reset_counters(&state.host_addr);
+    state.journal.clear();
+
+    StatusCode::NO_CONTENT
+}
@@ -0,0 +1,216 @@
+use std::convert::Infallible;
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use serde::{Serialize, Deserialize};
+
+use anyhow::{anyhow, Result};
+
+use axum::body::{boxed, Body, Bytes, Empty, StreamBody};
+use axum::http::Request;
+use axum::response::Response;
+
+use futures::{stream, Future};
+
+use tower::Service;
+
+use rand::{Rng, rngs::StdRng};
+
+use super::response::RenderedResponse;
+
+/// Faults injected in a response: a delay, and a failure happening with a probability.
+#[derive(Default, Debug ,Serialize, Deserialize, Clone, PartialEq)]
+pub struct Fault {
+    /// Delay before responding
+    #[serde(default)]
+    pub delay_ms: u64,
+    /// If set, the delay is random between delay_ms & delay_max_ms
+    #[serde(default)]
+    pub delay_max_ms: Option<u64>,
+    #[serde(default)]
+    pub failure: Option<Failure>,
+    /// Probability of the failure, between 0 and 100
+    #[serde(default = "default_failure_pct")]
+    pub failure_pct: f64,
+}
+
+fn default_failure_pct() -> f64 {
+    100.0
+}
+
+#[derive(Debug ,Serialize, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Failure {
+    /// Close the connection without responding, not even with a status line
+    CloseConnection,
+    /// Send the body in small chunks, slowly
+    SlowDrip {
+        #[serde(default = "default_chunk_bytes")]
+        chunk_bytes: usize,
+        #[serde(default = "default_interval_ms")]
+        interval_ms: u64,
+    },
+    /// Send a truncated body, which is not valid JSON
+    MalformedJson,
+}
+
+fn default_chunk_bytes() -> usize {
+    1
+}
+
+fn default_interval_ms() -> u64 {
+    100
+}
+
+impl Fault {
+    pub fn validate(&self) -> Result<()> {
+        if !(0.0..=100.0).contains(&self.failure_pct) {
+            return Err(anyhow!("fault.failure_pct must be between 0 and 100"));
+        }
+
+        if self.delay_max_ms.is_some_and(|m| m < self.delay_ms) {
+            return Err(anyhow!("fault.delay_max_ms cannot be lower than fault.delay_ms"));
+        }
+
+        if let Some(Failure::SlowDrip { chunk_bytes: 0, .. }) = self.failure {
+            return Err(anyhow!("fault.failure.slow_drip.chunk_bytes cannot be 0"));
+        }
+
+        Ok(())
+    }
+
+    /// Draw the delay and whether the failure happens.
+    pub fn plan(&self, rng: &mut StdRng) -> (Duration, Option<&Failure>) {
+        let delay = match self.delay_max_ms {
+            Some(max) => rng.gen_range(self.delay_ms..=max),
+            None => self.delay_ms,
+        };
+
+        let failure = self.failure.as_ref().filter(|_| rng.gen_bool(self.failure_pct / 100.0));
+
+        (Duration::from_millis(delay), failure)
+    }
+}
+
+impl Failure {
+    pub fn response(&self, response: RenderedResponse) -> Response {
+        match self {
+            Failure::CloseConnection => {
+                // Turned into a service error by CloseConnections, before anything is written
+                Response::builder()
+                    .extension(ConnectionClosed)
+                    .body(boxed(Empty::new()))
+                    .unwrap()
+            },
+            Failure::SlowDrip { chunk_bytes, interval_ms } => {
+                let chunks: Vec<Bytes> = response.body.chunks(*chunk_bytes).map(Bytes::copy_from_slice).collect();
+                let interval = Duration::from_millis(*interval_ms);
+
+                let body = stream::unfold(chunks.into_iter(), move |mut chunks| async move {
+                    let chunk = chunks.next()?;
+                    tokio::time::sleep(interval).await;
+
+                    Some((Ok::<Bytes, io::Error>(chunk), chunks))
+                });
+
+                let mut builder = Response::builder().status(response.status);
+                for (name, value) in response.headers.into_iter() {
+                    builder = builder.header(name, value);
+                }
+
+                builder.body(boxed(StreamBody::new(body))).unwrap()
+            },
+            Failure::MalformedJson => {
+                let mut body = response.body;
+                body.truncate(body.len() / 2);
+                body.extend_from_slice(b"{\"");
+
+                RenderedResponse { body, ..response }.into_response()
+            },
+        }
+    }
+}
+
+/// Marker of the responses whose connection must be closed without responding.
+#[derive(Debug, Clone, Copy)]
+struct ConnectionClosed;
+
+/// Whether the connection of the response is closed without responding.
+pub fn closes_connection(response: &Response) -> bool {
+    response.extensions().get::<ConnectionClosed>().is_some()
+}
+
+/// Service closing the connection of the responses marked by `Failure::CloseConnection`:
+/// hyper drops the connection of a failed service call without writing a response.
+#[derive(Clone)]
+pub struct CloseConnections<S>(pub S);
+
+impl<S> Service<Request<Body>> for CloseConnections<S>
+where
+    S: Service<Request<Body>, Response = Response, Error = Infallible>,
+    S::Future: Send + 'static,
+{
+    type Response = Response;
+    type Error = io::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Response, io::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.0.poll_ready(cx).map_err(|e| match e {})
+    }
+
+    fn call(&mut self, request: Request<Body>) -> Self::Future {
+        let response = self.0.call(request);
+
+        Box::pin(async move {
+            let response = match response.await {
+                Ok(r) => r,
+                Err(e) => match e {},
+            };
+
+            if closes_connection(&response) {
+                return Err(io::Error::new(io::ErrorKind::ConnectionReset, "fault: connection closed"));
+            }
+
+            Ok(response)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+
+    #[test]
+    fn fault_plan() {
+        let fault = Fault {
+            delay_ms: 10,
+            delay_max_ms: Some(50),
+            failure: Some(Failure::MalformedJson),
+            failure_pct: 50.0,
+        };
+        fault.validate().unwrap();
+
+        let draws = |seed| {
+            let mut rng = StdRng::seed_from_u64(seed);
+            (0..20).map(|_| fault.plan(&mut rng)).map(|(d, f)| (d, f.is_some())).collect::<Vec<(Duration, bool)>>()
+        };
+
+        // Reproducible with the same seed
+        assert_eq!(draws(7), draws(7));
+        assert!(draws(7).iter().all(|(d, _)| (10..=50).contains(&(d.as_millis() as u64))));
+        assert!(draws(7).iter().any(|(_, f)| *f) && draws(7).iter().any(|(_, f)| !*f));
+
+        assert!(Fault { failure_pct: 120.0, ..Default::default() }.validate().is_err());
+    }
+
+    #[test]
+    fn fault_closes_connection() {
+        let rendered = || RenderedResponse { status: axum::http::StatusCode::OK, headers: Vec::new(), body: b"{}".to_vec(), value: None };
+
+        assert!(closes_connection(&Failure::CloseConnection.response(rendered())));
+        assert!(!closes_connection(&Failure::MalformedJson.response(rendered())));
+    }
+}
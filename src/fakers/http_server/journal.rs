@@ -0,0 +1,194 @@
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use serde::{Serialize, Deserialize};
+use serde_json::Value as jsonValue;
+
+use anyhow::Result;
+
+use axum::http::{HeaderMap, Method, Uri};
+
+use chrono::Utc;
+
+use log::*;
+
+/// A request received by the server.
+#[derive(Default, Debug ,Serialize, Deserialize, Clone, PartialEq)]
+pub struct JournalEntry {
+    /// Arrival order of the request, from 0: entries are recorded once the response is sent,
+    /// so faults & concurrent requests may write them in another order
+    #[serde(default)]
+    pub sequence: u64,
+    /// Reception time in ms since the epoch
+    pub timestamp: i64,
+    pub method: String,
+    pub uri: String,
+    pub path: String,
+    pub query: BTreeMap<String, String>,
+    /// Lowercase header names
+    pub headers: BTreeMap<String, String>,
+    /// JSON body, or raw text if the body is not JSON
+    pub body: jsonValue,
+    /// None if no route nor resource matched the request
+    pub matched: Option<MatchedRoute>,
+    /// None if the connection was closed without responding
+    pub status: Option<u16>,
+    /// Differences with the closest route, if no route nor resource matched the request
+    #[serde(default)]
+    pub closest_match: Option<String>,
+}
+
+#[derive(Default, Debug ,Serialize, Deserialize, Clone, PartialEq)]
+pub struct MatchedRoute {
+    /// Id of the route, None for a resource
+    pub id: Option<usize>,
+    pub method: String,
+    pub path: String,
+}
+
+/// Value of a body: JSON if possible, raw text otherwise.
+pub fn body_value(body: &[u8]) -> jsonValue {
+    match serde_json::from_slice::<jsonValue>(body) {
+        Ok(v) => v,
+        Err(_) if body.is_empty() => jsonValue::Null,
+        Err(_) => jsonValue::String(String::from_utf8_lossy(body).to_string()),
+    }
+}
+
+impl JournalEntry {
+    pub fn new(method: &Method, uri: &Uri, query: &BTreeMap<String, String>, headers: &HeaderMap, body: &[u8]) -> Self {
+        JournalEntry {
+            sequence: 0,
+            timestamp: Utc::now().timestamp_millis(),
+            method: method.to_string(),
+            uri: uri.to_string(),
+            path: uri.path().to_string(),
+            query: query.clone(),
+            headers: headers.iter()
+                .filter_map(|(k, v)| v.to_str().ok().map(|v| (k.to_string(), v.to_string())))
+                .collect(),
+            body: body_value(body),
+            matched: None,
+            status: None,
+            closest_match: None,
+        }
+    }
+}
+
+/// Requests streamed to a JSON lines file, one entry per line,
+/// and kept in memory only for the admin API.
+pub struct Journal {
+    writer: Option<Mutex<BufWriter<File>>>,
+    entries: Option<Mutex<Vec<JournalEntry>>>,
+    next_sequence: AtomicU64,
+}
+
+impl Journal {
+    pub fn new(file: Option<&str>, keep_entries: bool) -> Result<Self> {
+        let writer = match file {
+            Some(f) => Some(Mutex::new(BufWriter::new(File::create(f)?))),
+            None => None,
+        };
+
+        let entries = keep_entries.then(|| Mutex::new(Vec::new()));
+
+        Ok(Journal { writer, entries, next_sequence: AtomicU64::new(0) })
+    }
+
+    /// Arrival sequence number of a new request.
+    pub fn next_sequence(&self) -> u64 {
+        self.next_sequence.fetch_add(1, Ordering::SeqCst)
+    }
+
+    /// Requests received so far, none if they are not kept.
+    pub fn entries(&self) -> Vec<JournalEntry> {
+        match &self.entries {
+            Some(e) => e.lock().unwrap().clone(),
+            None => Vec::new(),
+        }
+    }
+
+    /// Forget the requests received so far. The file keeps them.
+    pub fn clear(&self) {
+        if let Some(e) = &self.entries {
+            e.lock().unwrap().clear();
+        }
+    }
+
+    pub fn record(&self, entry: &JournalEntry) {
+        if let Some(e) = &self.entries {
+            e.lock().unwrap().push(entry.clone());
+        }
+
+        let writer = match &self.writer {
+            Some(w) => w,
+            None => return,
+        };
+
+        let line = match serde_json::to_string(entry) {
+            Ok(l) => l,
+            Err(e) => {
+                error!("http-server: journal entry: {}", e);
+                return;
+            },
+        };
+
+        let mut w = writer.lock().unwrap();
+        if let Err(e) = writeln!(w, "{}", line).and_then(|_| w.flush()) {
+            error!("http-server: writing the journal: {}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn journal_record() {
+        let file = "/tmp/httpserver_journal_test.jsonl";
+        let journal = Journal::new(Some(file), true).unwrap();
+
+        let mut headers = HeaderMap::new();
+        headers.insert("X-Tenant", "acme".parse().unwrap());
+
+        let uri: Uri = "/orders?page=2".parse().unwrap();
+        let query = BTreeMap::from([("page".to_string(), "2".to_string())]);
+
+        let mut entry = JournalEntry::new(&Method::POST, &uri, &query, &headers, br#"{"id": 1}"#);
+        journal.record(&entry);
+
+        entry.body = body_value(b"plain");
+        entry.status = Some(404);
+        journal.record(&entry);
+
+        let lines: Vec<JournalEntry> = std::fs::read_to_string(file).unwrap()
+            .lines()
+            .map(|l| serde_json::from_str(l).unwrap())
+            .collect();
+
+        assert_eq!(2, lines.len());
+        assert_eq!(lines, journal.entries());
+        assert_eq!("/orders", lines[0].path);
+        assert_eq!("acme", lines[0].headers["x-tenant"]);
+        assert_eq!(json!({"id": 1}), lines[0].body);
+        assert_eq!(json!("plain"), lines[1].body);
+        assert_eq!(Some(404), lines[1].status);
+    }
+
+    #[test]
+    fn journal_file_only() {
+        let file = "/tmp/httpserver_journal_file_only_test.jsonl";
+        let journal = Journal::new(Some(file), false).unwrap();
+
+        let uri: Uri = "/orders".parse().unwrap();
+        journal.record(&JournalEntry::new(&Method::GET, &uri, &BTreeMap::new(), &HeaderMap::new(), b""));
+
+        assert!(journal.entries().is_empty());
+        assert_eq!(1, std::fs::read_to_string(file).unwrap().lines().count());
+    }
+}
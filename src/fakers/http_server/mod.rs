@@ -1 +1,7 @@
 mod server;
+mod response;
+mod resource;
+mod fault;
+mod journal;
+mod admin;
+mod openapi;
@@ -0,0 +1,540 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+use serde_json::{json, Map, Value as jsonValue};
+
+use anyhow::{anyhow, Result};
+
+use axum::http::{HeaderMap, Method};
+
+use regex::Regex;
+
+use log::*;
+
+use crate::utils::path::match_path;
+use super::response::StubResponse;
+
+const METHODS: [&str; 8] = ["get", "put", "post", "delete", "options", "head", "patch", "trace"];
+
+/// Depth of the generated values, bounding the recursive schemas
+const MAX_DEPTH: usize = 8;
+
+/// Operations of an OpenAPI 3 specification, stubbed by the server and checked against the requests.
+#[derive(Debug, PartialEq)]
+pub struct Spec {
+    root: jsonValue,
+    operations: Vec<Operation>,
+}
+
+#[derive(Debug, PartialEq)]
+struct Operation {
+    /// Uppercase method
+    method: String,
+    /// Route path, `{name}` being turned into `:name`
+    path: String,
+    /// Parameters of the path item & of the operation, resolved
+    parameters: Vec<jsonValue>,
+    request_body: Option<jsonValue>,
+    responses: jsonValue,
+}
+
+/// Route path of an OpenAPI path, None if a parameter does not span a whole segment.
+fn route_path(path: &str) -> Option<String> {
+    let segments: Option<Vec<String>> = path.split('/')
+        .map(|s| match s.strip_prefix('{').and_then(|s| s.strip_suffix('}')) {
+            Some(name) => Some(format!(":{}", name)),
+            None if s.contains('{') || s.contains('}') => None,
+            None => Some(s.to_string()),
+        })
+        .collect();
+
+    segments.map(|s| s.join("/"))
+}
+
+/// Type of a schema, ignoring "null" in the 3.1 type lists.
+fn schema_type(schema: &jsonValue) -> Option<&str> {
+    match schema.get("type") {
+        Some(jsonValue::String(t)) => Some(t.as_str()),
+        Some(jsonValue::Array(types)) => types.iter().filter_map(|t| t.as_str()).find(|t| *t != "null"),
+        _ if schema.get("properties").is_some() => Some("object"),
+        _ => None,
+    }
+}
+
+fn is_nullable(schema: &jsonValue) -> bool {
+    schema.get("nullable").and_then(|n| n.as_bool()).unwrap_or(false)
+        || schema.get("type").and_then(|t| t.as_array()).is_some_and(|t| t.contains(&json!("null")))
+}
+
+/// Value of a parameter, converted to the type of its schema when possible.
+fn parameter_value(schema: &jsonValue, text: &str) -> jsonValue {
+    let converted = match schema_type(schema) {
+        Some("integer") => text.parse::<i64>().ok().map(|n| json!(n)),
+        Some("number") => text.parse::<f64>().ok().map(|n| json!(n)),
+        Some("boolean") => text.parse::<bool>().ok().map(|b| json!(b)),
+        Some("array") => {
+            let items = schema.get("items").cloned().unwrap_or(jsonValue::Null);
+            Some(jsonValue::Array(text.split(',').map(|t| parameter_value(&items, t)).collect()))
+        },
+        _ => None,
+    };
+
+    converted.unwrap_or_else(|| jsonValue::String(text.to_string()))
+}
+
+impl Spec {
+    pub fn load(file: &Path) -> Result<Self> {
+        let text = fs::read_to_string(file).map_err(|e| anyhow!("Reading OpenAPI spec {}: {}", file.display(), e))?;
+        let root: jsonValue = serde_yaml::from_str(&text).map_err(|e| anyhow!("Parsing OpenAPI spec {}: {}", file.display(), e))?;
+
+        Spec::new(root)
+    }
+
+    pub fn new(root: jsonValue) -> Result<Self> {
+        // 3.1 is read as a number from YAML
+        let version = match root.get("openapi") {
+            Some(jsonValue::String(v)) => v.clone(),
+            Some(v) => v.to_string(),
+            None => String::new(),
+        };
+
+        if !version.starts_with('3') {
+            return Err(anyhow!("Only OpenAPI 3 specifications are supported"));
+        }
+
+        let mut spec = Spec { root, operations: Vec::new() };
+
+        let paths = spec.root.get("paths").and_then(|p| p.as_object()).cloned().unwrap_or_default();
+
+        for (p, item) in paths.iter() {
+            let path = match route_path(p) {
+                Some(path) => path,
+                None => {
+                    warn!("OpenAPI path {} not supported: parameters must span whole segments", p);
+                    continue;
+                },
+            };
+
+            let item = spec.resolve(item);
+            let common = item.get("parameters").and_then(|p| p.as_array()).cloned().unwrap_or_default();
+
+            for m in METHODS.iter() {
+                let op = match item.get(m) {
+                    Some(op) => op,
+                    None => continue,
+                };
+
+                // Parameters of the operation override the ones of the path item
+                let mut parameters: Vec<jsonValue> = Vec::new();
+                let own = op.get("parameters").and_then(|p| p.as_array()).cloned().unwrap_or_default();
+                for param in own.iter().chain(common.iter()).map(|p| spec.resolve(p)) {
+                    if !parameters.iter().any(|q| q.get("name") == param.get("name") && q.get("in") == param.get("in")) {
+                        parameters.push(param);
+                    }
+                }
+
+                let request_body = op.get("requestBody").map(|b| spec.resolve(b));
+
+                spec.operations.push(Operation {
+                    method: m.to_uppercase(),
+                    path: path.clone(),
+                    parameters,
+                    request_body,
+                    responses: op.get("responses").cloned().unwrap_or(jsonValue::Null),
+                });
+            }
+        }
+
+        Ok(spec)
+    }
+
+    /// Follow the local `$ref` of a value.
+    fn resolve(&self, value: &jsonValue) -> jsonValue {
+        let mut v = value;
+
+        for _ in 0..MAX_DEPTH {
+            let target = v.get("$ref")
+                .and_then(|r| r.as_str())
+                .and_then(|r| r.strip_prefix('#'))
+                .and_then(|r| self.root.pointer(r));
+
+            match target {
+                Some(t) => v = t,
+                None => break,
+            }
+        }
+
+        v.clone()
+    }
+
+    /// Method, path & response of every operation: the first 2xx response, with its example or a value generated from its schema.
+    pub fn stubs(&self) -> Vec<(String, String, StubResponse)> {
+        self.operations.iter()
+            .map(|op| (op.method.clone(), op.path.clone(), self.response(op)))
+            .collect()
+    }
+
+    fn response(&self, op: &Operation) -> StubResponse {
+        let codes: Vec<&String> = op.responses.as_object().map(|r| r.keys().collect()).unwrap_or_default();
+
+        let code = codes.iter().find(|c| c.starts_with('2'))
+            .or_else(|| codes.iter().find(|c| c.as_str() == "default"))
+            .or_else(|| codes.first());
+
+        let code = match code {
+            Some(c) => *c,
+            None => return StubResponse { status: 200, ..Default::default() },
+        };
+
+        // 2XX & default ranges are served as 200
+        let status = code.parse::<u16>().unwrap_or(200);
+        let response = self.resolve(&op.responses[code]);
+
+        let content = response.get("content").and_then(|c| c.as_object());
+        let media = content.and_then(|c| {
+            c.iter().find(|(t, _)| t.contains("json")).or_else(|| c.iter().next())
+        });
+
+        let (content_type, media) = match media {
+            Some((t, m)) => (t.clone(), m),
+            None => return StubResponse { status, ..Default::default() },
+        };
+
+        let value = media.get("example").cloned()
+            .or_else(|| {
+                media.get("examples")
+                    .and_then(|e| e.as_object())
+                    .and_then(|e| e.values().next())
+                    .map(|e| self.resolve(e))
+                    .and_then(|e| e.get("value").cloned())
+            })
+            .or_else(|| media.get("schema").map(|s| self.generate(s, 0)));
+
+        let result = value.map(|v| match v {
+            jsonValue::String(s) if !content_type.contains("json") => s,
+            v => v.to_string(),
+        });
+
+        // Examples are not templates: `{{` must be sent as is
+        StubResponse { status, content_type: Some(content_type), result, raw: true, ..Default::default() }
+    }
+
+    /// Generate a value of a schema, using its example, default or first enum value when set.
+    fn generate(&self, schema: &jsonValue, depth: usize) -> jsonValue {
+        let schema = self.resolve(schema);
+
+        if let Some(v) = schema.get("example").or_else(|| schema.get("default")).or_else(|| schema.get("enum").and_then(|e| e.get(0))) {
+            return v.clone();
+        }
+
+        if depth > MAX_DEPTH {
+            return jsonValue::Null;
+        }
+
+        if let Some(all) = schema.get("allOf").and_then(|a| a.as_array()) {
+            let mut v = Map::new();
+
+            for s in all.iter() {
+                if let jsonValue::Object(o) = self.generate(s, depth + 1) {
+                    v.extend(o);
+                }
+            }
+
+            return jsonValue::Object(v);
+        }
+
+        if let Some(s) = schema.get("oneOf").or_else(|| schema.get("anyOf")).and_then(|a| a.get(0)) {
+            return self.generate(s, depth + 1);
+        }
+
+        match schema_type(&schema) {
+            Some("object") => {
+                let mut v = Map::new();
+
+                if let Some(props) = schema.get("properties").and_then(|p| p.as_object()) {
+                    for (name, s) in props.iter() {
+                        v.insert(name.clone(), self.generate(s, depth + 1));
+                    }
+                }
+
+                jsonValue::Object(v)
+            },
+            Some("array") => match schema.get("items") {
+                Some(items) => json!([self.generate(items, depth + 1)]),
+                None => json!([]),
+            },
+            Some("string") => match schema.get("format").and_then(|f| f.as_str()) {
+                Some("date-time") => json!("1970-01-01T00:00:00Z"),
+                Some("date") => json!("1970-01-01"),
+                Some("uuid") => json!("00000000-0000-0000-0000-000000000000"),
+                Some("email") => json!("user@example.com"),
+                Some("uri") => json!("http://example.com"),
+                _ => json!("string"),
+            },
+            Some("integer") => json!(schema.get("minimum").and_then(|m| m.as_i64()).unwrap_or(0)),
+            Some("number") => json!(schema.get("minimum").and_then(|m| m.as_f64()).unwrap_or(0.0)),
+            Some("boolean") => json!(true),
+            _ => jsonValue::Null,
+        }
+    }
+
+    /// Check a value against a schema, adding the violations found at the given location.
+    fn check(&self, schema: &jsonValue, value: &jsonValue, at: &str, violations: &mut Vec<String>) {
+        let schema = self.resolve(schema);
+
+        if value.is_null() && is_nullable(&schema) {
+            return;
+        }
+
+        if let Some(all) = schema.get("allOf").and_then(|a| a.as_array()) {
+            for s in all.iter() {
+                self.check(s, value, at, violations);
+            }
+        }
+
+        if let Some(any) = schema.get("oneOf").or_else(|| schema.get("anyOf")).and_then(|a| a.as_array()) {
+            let matching = any.iter().any(|s| {
+                let mut v = Vec::new();
+                self.check(s, value, at, &mut v);
+                v.is_empty()
+            });
+
+            if !matching {
+                violations.push(format!("{}: does not match any of the schemas", at));
+            }
+        }
+
+        if let Some(values) = schema.get("enum").and_then(|e| e.as_array()) {
+            if !values.contains(value) {
+                violations.push(format!("{}: {} is not one of {}", at, value, jsonValue::Array(values.clone())));
+            }
+        }
+
+        let expected = match schema_type(&schema) {
+            Some(t) => t,
+            None => return,
+        };
+
+        let type_ok = match expected {
+            "object" => value.is_object(),
+            "array" => value.is_array(),
+            "string" => value.is_string(),
+            "integer" => value.is_i64() || value.is_u64(),
+            "number" => value.is_number(),
+            "boolean" => value.is_boolean(),
+            _ => true,
+        };
+
+        if !type_ok {
+            violations.push(format!("{}: expected {}, got {}", at, expected, value));
+            return;
+        }
+
+        match value {
+            jsonValue::Object(o) => {
+                for name in schema.get("required").and_then(|r| r.as_array()).into_iter().flatten().filter_map(|n| n.as_str()) {
+                    if !o.contains_key(name) {
+                        violations.push(format!("{}: missing required property {}", at, name));
+                    }
+                }
+
+                if let Some(props) = schema.get("properties").and_then(|p| p.as_object()) {
+                    for (name, v) in o.iter() {
+                        if let Some(s) = props.get(name) {
+                            self.check(s, v, &format!("{}.{}", at, name), violations);
+                        }
+                    }
+                }
+            },
+            jsonValue::Array(items) => {
+                if let Some(min) = schema.get("minItems").and_then(|m| m.as_u64()) {
+                    if (items.len() as u64) < min {
+                        violations.push(format!("{}: expected at least {} items", at, min));
+                    }
+                }
+
+                if let Some(max) = schema.get("maxItems").and_then(|m| m.as_u64()) {
+                    if (items.len() as u64) > max {
+                        violations.push(format!("{}: expected at most {} items", at, max));
+                    }
+                }
+
+                if let Some(s) = schema.get("items") {
+                    for (i, v) in items.iter().enumerate() {
+                        self.check(s, v, &format!("{}[{}]", at, i), violations);
+                    }
+                }
+            },
+            jsonValue::String(s) => {
+                let len = s.chars().count() as u64;
+
+                if schema.get("minLength").and_then(|m| m.as_u64()).is_some_and(|m| len < m) {
+                    violations.push(format!("{}: {:?} is shorter than {}", at, s, schema["minLength"]));
+                }
+
+                if schema.get("maxLength").and_then(|m| m.as_u64()).is_some_and(|m| len > m) {
+                    violations.push(format!("{}: {:?} is longer than {}", at, s, schema["maxLength"]));
+                }
+
+                if let Some(p) = schema.get("pattern").and_then(|p| p.as_str()) {
+                    match Regex::new(p) {
+                        Ok(re) if !re.is_match(s) => violations.push(format!("{}: {:?} does not match {}", at, s, p)),
+                        Ok(_) => {},
+                        Err(e) => warn!("Invalid OpenAPI pattern {}: {}", p, e),
+                    }
+                }
+            },
+            jsonValue::Number(n) => {
+                let n = n.as_f64().unwrap_or_default();
+
+                if schema.get("minimum").and_then(|m| m.as_f64()).is_some_and(|m| n < m) {
+                    violations.push(format!("{}: {} is lower than {}", at, n, schema["minimum"]));
+                }
+
+                if schema.get("maximum").and_then(|m| m.as_f64()).is_some_and(|m| n > m) {
+                    violations.push(format!("{}: {} is greater than {}", at, n, schema["maximum"]));
+                }
+            },
+            _ => {},
+        }
+    }
+
+    /// Check a request against its operation: parameters & body.
+    /// Requests of paths not described by the specification are not checked.
+    pub fn validate_request(&self, method: &Method, path: &str, query: &BTreeMap<String, String>, headers: &HeaderMap, body: &[u8]) -> Result<(), Vec<String>> {
+        let (op, path_params) = match self.operations.iter()
+            .filter(|op| op.method == method.as_str())
+            .find_map(|op| match_path(&op.path, path).map(|p| (op, p))) {
+            Some(o) => o,
+            None => return Ok(()),
+        };
+
+        let mut violations = Vec::new();
+
+        for param in op.parameters.iter() {
+            let name = param.get("name").and_then(|n| n.as_str()).unwrap_or_default();
+            let location = param.get("in").and_then(|i| i.as_str()).unwrap_or_default();
+
+            let text = match location {
+                "path" => path_params.get(name).and_then(|v| v.as_str()).map(|v| v.to_string()),
+                "query" => query.get(name).cloned(),
+                "header" => headers.get(name).and_then(|v| v.to_str().ok()).map(|v| v.to_string()),
+                _ => continue,
+            };
+
+            let schema = param.get("schema").cloned().unwrap_or(jsonValue::Null);
+
+            match text {
+                Some(t) => self.check(&schema, &parameter_value(&self.resolve(&schema), &t), &format!("{} parameter {}", location, name), &mut violations),
+                None if param.get("required").and_then(|r| r.as_bool()).unwrap_or(false) => {
+                    violations.push(format!("{} parameter {}: missing", location, name));
+                },
+                None => {},
+            }
+        }
+
+        if let Some(request_body) = &op.request_body {
+            let required = request_body.get("required").and_then(|r| r.as_bool()).unwrap_or(false);
+            let schema = request_body.get("content")
+                .and_then(|c| c.as_object())
+                .and_then(|c| c.iter().find(|(t, _)| t.contains("json")))
+                .and_then(|(_, m)| m.get("schema"));
+
+            match (body.is_empty(), schema) {
+                (true, _) if required => violations.push("body: missing".to_string()),
+                (false, Some(s)) => match serde_json::from_slice::<jsonValue>(body) {
+                    Ok(v) => self.check(s, &v, "$", &mut violations),
+                    Err(e) => violations.push(format!("body: invalid JSON: {}", e)),
+                },
+                _ => {},
+            }
+        }
+
+        if violations.is_empty() {
+            Ok(())
+        } else {
+            Err(violations)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn openapi_spec() {
+        let spec = Spec::new(serde_yaml::from_str(r#"
+openapi: 3.0.3
+paths:
+  /pets/{petId}:
+    parameters:
+    - {name: petId, in: path, required: true, schema: {type: integer}}
+    get:
+      responses:
+        '404': {description: not found}
+        '200':
+          description: a pet
+          content:
+            application/json:
+              example: {id: 1, name: 'rex {{x}}'}
+  /pets:
+    post:
+      parameters:
+      - {name: dry_run, in: query, schema: {type: boolean}}
+      requestBody:
+        required: true
+        content:
+          application/json:
+            schema: {$ref: '#/components/schemas/Pet'}
+      responses:
+        '201':
+          description: created
+          content:
+            application/json:
+              schema: {$ref: '#/components/schemas/Pet'}
+components:
+  schemas:
+    Pet:
+      type: object
+      required: [name]
+      properties:
+        id: {type: integer, minimum: 1}
+        name: {type: string, minLength: 1}
+        tags: {type: array, items: {type: string}}
+        born: {type: string, format: date}
+"#).unwrap()).unwrap();
+
+        let stubs = spec.stubs();
+        assert_eq!(2, stubs.len());
+
+        let (method, path, response) = stubs.iter().find(|(m, _, _)| m == "GET").unwrap();
+        assert_eq!(("GET", "/pets/:petId", 200), (method.as_str(), path.as_str(), response.status));
+        assert_eq!(json!({"id": 1, "name": "rex {{x}}"}), serde_json::from_str::<jsonValue>(response.result.as_ref().unwrap()).unwrap());
+        assert_eq!(br#"{"id":1,"name":"rex {{x}}"}"#.to_vec(), response.render(&json!({})).unwrap().body);
+
+        let (_, _, response) = stubs.iter().find(|(m, _, _)| m == "POST").unwrap();
+        assert_eq!(201, response.status);
+        assert_eq!(json!({"id": 1, "name": "string", "tags": ["string"], "born": "1970-01-01"}), serde_json::from_str::<jsonValue>(response.result.as_ref().unwrap()).unwrap());
+
+        let headers = HeaderMap::new();
+        let no_query = BTreeMap::new();
+        let dry_run = BTreeMap::from([("dry_run".to_string(), "maybe".to_string())]);
+
+        assert!(spec.validate_request(&Method::GET, "/pets/1", &no_query, &headers, b"").is_ok());
+        assert!(spec.validate_request(&Method::GET, "/unknown", &no_query, &headers, b"").is_ok());
+        assert_eq!(1, spec.validate_request(&Method::GET, "/pets/rex", &no_query, &headers, b"").unwrap_err().len());
+
+        assert!(spec.validate_request(&Method::POST, "/pets", &no_query, &headers, br#"{"name": "rex", "tags": ["a"]}"#).is_ok());
+        assert_eq!(vec!["body: missing"], spec.validate_request(&Method::POST, "/pets", &no_query, &headers, b"").unwrap_err());
+
+        let violations = spec.validate_request(&Method::POST, "/pets", &dry_run, &headers, br#"{"id": 0, "tags": [1]}"#).unwrap_err();
+        assert_eq!(vec![
+            "query parameter dry_run: expected boolean, got \"maybe\"",
+            "$: missing required property name",
+            "$.id: 0 is lower than 1",
+            "$.tags[0]: expected string, got 1",
+        ], violations);
+    }
+}
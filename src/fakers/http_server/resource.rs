@@ -0,0 +1,314 @@
+use std::fs::File;
+use std::io::Write;
+
+use serde::{Serialize, Deserialize};
+use serde_json::{json, Map, Value as jsonValue};
+
+use anyhow::{anyhow, Result};
+
+use axum::extract::Json;
+use axum::http::{header, Method, StatusCode};
+use axum::response::{Headers, IntoResponse, Response};
+
+use uuid::Uuid;
+
+use log::*;
+
+/// REST collection served from memory: POST creates, GET lists or fetches, PUT replaces,
+/// PATCH merges and DELETE removes the items.
+#[derive(Default, Debug ,Serialize, Deserialize, Clone, PartialEq)]
+pub struct Resource {
+    /// Path of the collection, the items being at `<path>/<id>`
+    pub path: String,
+    #[serde(default = "default_id_field")]
+    pub id_field: String,
+    /// Generation of the ids of the created items
+    #[serde(default)]
+    pub id_type: IdType,
+    /// Initial items of the collection
+    #[serde(default)]
+    pub seed: Vec<jsonValue>,
+}
+
+fn default_id_field() -> String {
+    "id".to_string()
+}
+
+#[derive(Default, Debug ,Serialize, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum IdType {
+    /// 1, 2, 3... following the largest numeric id of the seed
+    #[default]
+    Sequence,
+    Uuid,
+}
+
+impl Resource {
+    pub fn validate(&self) -> Result<()> {
+        if !self.path.starts_with('/') || self.path.ends_with('/') {
+            return Err(anyhow!("Resource path {} must start and must not end with /", self.path));
+        }
+
+        if self.path.contains(':') || self.path.contains('*') {
+            return Err(anyhow!("Resource path {} cannot contain parameters", self.path));
+        }
+
+        for item in self.seed.iter() {
+            if item.get(&self.id_field).is_none_or(|id| id.is_null()) {
+                return Err(anyhow!("Seed items of {} must have an {}: {}", self.path, self.id_field, item));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Text of an id, numbers & strings being equivalent in the URI.
+fn id_text(id: &jsonValue) -> String {
+    match id {
+        jsonValue::String(s) => s.clone(),
+        v => v.to_string(),
+    }
+}
+
+/// Apply a JSON merge patch (RFC 7386).
+fn merge_patch(target: &mut jsonValue, patch: &jsonValue) {
+    match (target, patch) {
+        (jsonValue::Object(t), jsonValue::Object(p)) => {
+            for (k, v) in p.iter() {
+                if v.is_null() {
+                    t.remove(k);
+                } else {
+                    merge_patch(t.entry(k.clone()).or_insert(jsonValue::Null), v);
+                }
+            }
+        },
+        (t, p) => *t = p.clone(),
+    }
+}
+
+struct Collection {
+    resource: Resource,
+    items: Vec<jsonValue>,
+    next_id: u64,
+}
+
+impl Collection {
+    fn position(&self, id: &str) -> Option<usize> {
+        self.items.iter().position(|i| i.get(&self.resource.id_field).is_some_and(|v| id_text(v) == id))
+    }
+
+    fn generate_id(&mut self) -> jsonValue {
+        match self.resource.id_type {
+            IdType::Uuid => jsonValue::String(Uuid::new_v4().to_string()),
+            IdType::Sequence => {
+                let id = self.next_id;
+                self.next_id += 1;
+                json!(id)
+            },
+        }
+    }
+
+    fn handle(&mut self, method: &Method, id: Option<&str>, body: &[u8]) -> Response {
+        let id_field = self.resource.id_field.clone();
+
+        let payload = match *method {
+            Method::POST | Method::PUT | Method::PATCH => match serde_json::from_slice::<jsonValue>(body) {
+                Ok(v @ jsonValue::Object(_)) => Some(v),
+                _ => return (StatusCode::BAD_REQUEST, Json(json!({"error": "the body must be a JSON object"}))).into_response(),
+            },
+            _ => None,
+        };
+
+        match (method, id, payload) {
+            (&Method::GET, None, _) => Json(jsonValue::Array(self.items.clone())).into_response(),
+            (&Method::POST, None, Some(mut item)) => {
+                let id = match item.get(&id_field) {
+                    Some(id) if !id.is_null() => id.clone(),
+                    _ => self.generate_id(),
+                };
+
+                if self.position(&id_text(&id)).is_some() {
+                    return (StatusCode::CONFLICT, Json(json!({"error": format!("{} {} already exists", id_field, id_text(&id))}))).into_response();
+                }
+
+                // Ids supplied by the client are never generated afterwards
+                if let Ok(n) = id_text(&id).parse::<u64>() {
+                    self.next_id = self.next_id.max(n + 1);
+                }
+
+                item[&id_field] = id.clone();
+                self.items.push(item.clone());
+
+                let location = format!("{}/{}", self.resource.path, id_text(&id));
+                (StatusCode::CREATED, Headers(vec![(header::LOCATION, location)]), Json(item)).into_response()
+            },
+            (_, Some(id), payload) => {
+                let pos = match self.position(id) {
+                    Some(p) => p,
+                    None => return (StatusCode::NOT_FOUND, Json(json!({"error": format!("{} {} not found", id_field, id)}))).into_response(),
+                };
+
+                match (method, payload) {
+                    (&Method::GET, _) => Json(self.items[pos].clone()).into_response(),
+                    (&Method::PUT, Some(mut item)) => {
+                        // The id of the URI prevails
+                        item[&id_field] = self.items[pos][&id_field].clone();
+                        self.items[pos] = item.clone();
+
+                        Json(item).into_response()
+                    },
+                    (&Method::PATCH, Some(patch)) => {
+                        // The id cannot be patched
+                        let id_value = self.items[pos][&id_field].clone();
+                        merge_patch(&mut self.items[pos], &patch);
+                        self.items[pos][&id_field] = id_value;
+
+                        Json(self.items[pos].clone()).into_response()
+                    },
+                    (&Method::DELETE, _) => {
+                        self.items.remove(pos);
+
+                        StatusCode::NO_CONTENT.into_response()
+                    },
+                    _ => StatusCode::METHOD_NOT_ALLOWED.into_response(),
+                }
+            },
+            _ => StatusCode::METHOD_NOT_ALLOWED.into_response(),
+        }
+    }
+}
+
+/// In-memory state of the resources.
+pub struct ResourceStore {
+    collections: Vec<Collection>,
+    output_file: Option<String>,
+}
+
+impl ResourceStore {
+    pub fn new(resources: &[Resource], output_file: Option<String>) -> Self {
+        let collections = resources.iter()
+            .map(|r| {
+                let max_id = r.seed.iter()
+                    .filter_map(|i| i.get(&r.id_field).and_then(|id| id_text(id).parse::<u64>().ok()))
+                    .max()
+                    .unwrap_or(0);
+
+                Collection { resource: r.clone(), items: r.seed.clone(), next_id: max_id + 1 }
+            })
+            .collect();
+
+        ResourceStore { collections, output_file }
+    }
+
+    /// Serve a request on a resource, returning the path of the resource and the response.
+    /// None if the path does not belong to any resource.
+    pub fn handle(&mut self, method: &Method, path: &str, body: &[u8]) -> Option<(String, Response)> {
+        let path = path.trim_end_matches('/');
+
+        let (collection, id) = self.collections.iter_mut().find_map(|c| {
+            if path == c.resource.path {
+                return Some((c, None));
+            }
+
+            path.strip_prefix(c.resource.path.as_str())
+                .and_then(|rest| rest.strip_prefix('/'))
+                .filter(|id| !id.is_empty() && !id.contains('/'))
+                .map(|id| (c, Some(id)))
+        })?;
+
+        let resource = collection.resource.path.clone();
+        let response = collection.handle(method, id, body);
+
+        if [Method::POST, Method::PUT, Method::PATCH, Method::DELETE].contains(method) {
+            if let Err(e) = self.dump() {
+                error!("http-server: writing the resources: {}", e);
+            }
+        }
+
+        Some((resource, response))
+    }
+
+    /// Items of each resource, by path.
+    pub fn snapshot(&self) -> jsonValue {
+        let mut v = Map::new();
+
+        for c in self.collections.iter() {
+            v.insert(c.resource.path.clone(), jsonValue::Array(c.items.clone()));
+        }
+
+        jsonValue::Object(v)
+    }
+
+    /// Write the items to the output file: it always holds the latest state of the resources.
+    pub fn dump(&self) -> Result<()> {
+        if let Some(o) = &self.output_file {
+            let mut f = File::create(o)?;
+            let text = serde_json::to_string_pretty(&self.snapshot())?;
+
+            f.write_all(text.as_bytes())?;
+            f.write_all(b"\n")?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resource_merge_patch() {
+        let mut target = json!({"a": 1, "b": {"c": 2, "d": 3}});
+
+        merge_patch(&mut target, &json!({"a": null, "b": {"c": 4}, "e": 5}));
+
+        assert_eq!(json!({"b": {"c": 4, "d": 3}, "e": 5}), target);
+    }
+
+    #[test]
+    fn resource_crud() {
+        let resource: Resource = serde_yaml::from_str(r#"
+path: /orders
+seed:
+- id: 1
+  status: new
+"#).unwrap();
+        resource.validate().unwrap();
+
+        let mut store = ResourceStore::new(&[resource], None);
+
+        assert!(store.handle(&Method::GET, "/users", b"").is_none());
+        assert_eq!(StatusCode::OK, store.handle(&Method::GET, "/orders/1", b"").unwrap().1.status());
+
+        let created = store.handle(&Method::POST, "/orders", br#"{"status": "new"}"#).unwrap();
+        assert_eq!(StatusCode::CREATED, created.1.status());
+        assert_eq!("/orders/2", created.1.headers()[header::LOCATION]);
+
+        assert_eq!(StatusCode::OK, store.handle(&Method::PATCH, "/orders/2", br#"{"status": "paid"}"#).unwrap().1.status());
+        assert_eq!(StatusCode::BAD_REQUEST, store.handle(&Method::PUT, "/orders/2", b"oops").unwrap().1.status());
+        assert_eq!(json!({"/orders": [{"id": 1, "status": "new"}, {"id": 2, "status": "paid"}]}), store.snapshot());
+
+        assert_eq!(StatusCode::NO_CONTENT, store.handle(&Method::DELETE, "/orders/1", b"").unwrap().1.status());
+        assert_eq!(StatusCode::NOT_FOUND, store.handle(&Method::GET, "/orders/1", b"").unwrap().1.status());
+        assert_eq!(StatusCode::NOT_FOUND, store.handle(&Method::DELETE, "/orders/1", b"").unwrap().1.status());
+    }
+
+    #[test]
+    fn resource_explicit_ids() {
+        let resource: Resource = serde_yaml::from_str("path: /orders").unwrap();
+        let mut store = ResourceStore::new(&[resource], None);
+
+        let created = store.handle(&Method::POST, "/orders", br#"{"id": 3}"#).unwrap();
+        assert_eq!(StatusCode::CREATED, created.1.status());
+        assert_eq!(StatusCode::CONFLICT, store.handle(&Method::POST, "/orders", br#"{"id": 3}"#).unwrap().1.status());
+
+        // The sequence continues after the explicit id
+        for expected in ["/orders/4", "/orders/5", "/orders/6"] {
+            let created = store.handle(&Method::POST, "/orders", br#"{"status": "new"}"#).unwrap();
+            assert_eq!(StatusCode::CREATED, created.1.status());
+            assert_eq!(expected, created.1.headers()[header::LOCATION]);
+        }
+    }
+}
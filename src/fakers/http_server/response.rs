@@ -0,0 +1,193 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+use serde::{Serialize, Deserialize};
+use serde_json::Value as jsonValue;
+
+use anyhow::{anyhow, Result};
+
+use axum::body::{boxed, Full};
+use axum::http::{header, header::HeaderName, HeaderValue, StatusCode};
+use axum::response::Response;
+
+use crate::utils::template;
+use super::fault::Fault;
+
+/// Response returned by a route.
+#[derive(Default, Debug ,Serialize, Deserialize, Clone, PartialEq)]
+pub struct StubResponse {
+    #[serde(default = "default_status")]
+    pub status: u16,
+    /// Headers of the response, such as Location or Set-Cookie
+    #[serde(default)]
+    pub response_headers: BTreeMap<String, HeaderValues>,
+    /// Content type of the result, guessed if not set: JSON if the result is valid JSON, plain text otherwise
+    #[serde(default)]
+    pub content_type: Option<String>,
+    /// Body template of the response
+    #[serde(default)]
+    pub result: Option<String>,
+    /// Send the result as is, without rendering it as a template
+    #[serde(default)]
+    pub raw: bool,
+    /// File sent as is as the body of the response, relative to the scenario or to the routes file
+    #[serde(default)]
+    pub result_file: Option<String>,
+    #[serde(default)]
+    pub fault: Option<Fault>,
+}
+
+fn default_status() -> u16 {
+    200
+}
+
+/// One or several values of a header.
+#[derive(Debug ,Serialize, Deserialize, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum HeaderValues {
+    One(String),
+    Many(Vec<String>),
+}
+
+impl HeaderValues {
+    fn values(&self) -> Vec<&str> {
+        match self {
+            HeaderValues::One(v) => vec![v.as_str()],
+            HeaderValues::Many(v) => v.iter().map(|v| v.as_str()).collect(),
+        }
+    }
+}
+
+/// A response ready to be sent.
+pub struct RenderedResponse {
+    pub status: StatusCode,
+    pub headers: Vec<(HeaderName, HeaderValue)>,
+    pub body: Vec<u8>,
+    /// Value of the body, for JSON & text bodies
+    pub value: Option<jsonValue>,
+}
+
+impl StubResponse {
+    pub fn validate(&self) -> Result<()> {
+        StatusCode::from_u16(self.status).map_err(|e| anyhow!("Invalid status {}: {}", self.status, e))?;
+
+        if self.result.is_some() && self.result_file.is_some() {
+            return Err(anyhow!("result and result_file cannot be both set"));
+        }
+
+        for name in self.response_headers.keys() {
+            HeaderName::from_bytes(name.as_bytes()).map_err(|e| anyhow!("Invalid header name {}: {}", name, e))?;
+        }
+
+        if let Some(f) = &self.result_file {
+            fs::metadata(f).map_err(|e| anyhow!("result_file {}: {}", f, e))?;
+        }
+
+        if let Some(f) = &self.fault {
+            f.validate()?;
+        }
+
+        Ok(())
+    }
+
+    /// Resolve a relative result_file against the given directory.
+    pub fn resolve_paths(&mut self, dir: &Path) {
+        if let Some(f) = &self.result_file {
+            self.result_file = Some(dir.join(f).to_string_lossy().to_string());
+        }
+    }
+
+    /// Render the body & the headers of the response with the given template context.
+    pub fn render(&self, context: &jsonValue) -> Result<RenderedResponse> {
+        let status = StatusCode::from_u16(self.status)?;
+
+        let mut headers = Vec::new();
+
+        let (body, value, guessed_type) = match (&self.result, &self.result_file) {
+            (Some(res), _) => {
+                let text = match self.raw {
+                    true => res.clone(),
+                    false => template::render(res, context)?,
+                };
+
+                match serde_json::from_str::<jsonValue>(&text) {
+                    Ok(v) => (text.into_bytes(), Some(v), Some("application/json")),
+                    Err(_) => {
+                        let v = jsonValue::String(text.clone());
+                        (text.into_bytes(), Some(v), Some("text/plain; charset=utf-8"))
+                    },
+                }
+            },
+            (None, Some(f)) => (fs::read(f).map_err(|e| anyhow!("Reading {}: {}", f, e))?, None, Some("application/octet-stream")),
+            (None, None) => (vec![], None, None),
+        };
+
+        if let Some(t) = self.content_type.as_deref().or(guessed_type) {
+            headers.push((header::CONTENT_TYPE, HeaderValue::from_str(t)?));
+        }
+
+        for (name, values) in self.response_headers.iter() {
+            let name = HeaderName::from_bytes(name.as_bytes())?;
+
+            for v in values.values() {
+                headers.push((name.clone(), HeaderValue::from_str(&template::render(v, context)?)?));
+            }
+        }
+
+        Ok(RenderedResponse { status, headers, body, value })
+    }
+}
+
+impl RenderedResponse {
+    pub fn into_response(self) -> Response {
+        let mut builder = Response::builder().status(self.status);
+
+        for (name, value) in self.headers.into_iter() {
+            builder = builder.header(name, value);
+        }
+
+        builder.body(boxed(Full::from(self.body))).unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn response_render() {
+        let response: StubResponse = serde_yaml::from_str(r#"
+status: 201
+response_headers:
+  Location: /users/{{params.id}}
+  Set-Cookie:
+  - a=1
+  - b=2
+result: '{"id": "{{params.id}}"}'
+"#).unwrap();
+        response.validate().unwrap();
+
+        let rendered = response.render(&json!({"params": {"id": "7"}})).unwrap();
+
+        assert_eq!(StatusCode::CREATED, rendered.status);
+        assert_eq!(Some(json!({"id": "7"})), rendered.value);
+        assert!(rendered.headers.contains(&(header::CONTENT_TYPE, HeaderValue::from_static("application/json"))));
+        assert_eq!(2, rendered.headers.iter().filter(|(n, _)| *n == header::SET_COOKIE).count());
+
+        let response = StubResponse {
+            status: 200,
+            content_type: Some("application/xml".to_string()),
+            result: Some("<user>bob</user>".to_string()),
+            ..Default::default()
+        };
+        let rendered = response.render(&json!({})).unwrap();
+
+        assert_eq!(b"<user>bob</user>".to_vec(), rendered.body);
+        assert!(rendered.headers.contains(&(header::CONTENT_TYPE, HeaderValue::from_static("application/xml"))));
+
+        let rendered = StubResponse { status: 204, ..Default::default() }.render(&json!({})).unwrap();
+        assert!(rendered.body.is_empty() && rendered.headers.is_empty());
+    }
+}
@@ -1,12 +1,13 @@
 use std::net::{SocketAddr, SocketAddrV4};
-use std::sync::{Arc, Mutex};
-use std::fs::File;
+use std::sync::{Arc, Mutex, RwLock};
+use std::fs::{self, File};
 use std::io::Write;
+use std::path::{Path, PathBuf};
 
-//use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use serde::{Serialize, Deserialize};
 use serde_yaml::{Mapping, Value as yamlValue};
-use serde_json::{Value as jsonValue, Map};
+use serde_json::{json, Value as jsonValue, Map};
 
 use anyhow::{anyhow, Result, Error};
 
@@ -19,31 +20,283 @@ use log::*;
 
 use axum::Router;
 use axum::routing::*;
-use axum::extract::{Json, Extension, MatchedPath};
-use axum::response::IntoResponse;
-use axum::http::StatusCode;
+use axum::body::Bytes;
+use axum::extract::{Json, Extension, Query};
+use axum::response::{IntoResponse, Response};
+use axum::http::{HeaderMap, Method, StatusCode, Uri};
+
+use tower::make::Shared;
 
 use futures::{Future, future};
 use std::pin::Pin;
+use std::time::Duration;
 
 
 use crate::faker::{Faker, FakerMod};
+use crate::utils::matcher::{BodyMatcher, Matcher};
+use crate::utils::path::{match_path, validate_pattern};
+use crate::utils::template;
+use crate::utils::unmatched;
+use super::fault::{self, CloseConnections};
+use super::response::StubResponse;
+use super::resource::{Resource, ResourceStore};
+use super::journal::{body_value, Journal, JournalEntry, MatchedRoute};
+use super::openapi::Spec;
+use super::admin;
+
+use rand::{SeedableRng, rngs::StdRng};
+use tokio::time::sleep;
+
+/// Method of the routes matching any request method
+const ANY_METHOD: &str = "ANY";
+
+const METHODS: [&str; 10] = ["GET", "POST", "PUT", "DELETE", "PATCH", "HEAD", "OPTIONS", "TRACE", "CONNECT", ANY_METHOD];
 
 // Our plugin implementation
 #[derive(Default, Debug ,Serialize, Deserialize, Clone, PartialEq)]
 struct HttpServer {
     host_addr: String,
+    /// Inline routes, tried before the routes of routes_file & routes_dir
+    #[serde(default)]
     routes: Vec<Route>,
-    output_file: Option<String>
+    /// YAML or JSON file holding a list of routes, tried after the inline routes
+    #[serde(default)]
+    routes_file: Option<String>,
+    /// Directory of YAML or JSON route files, tried last in the order of their names
+    #[serde(default)]
+    routes_dir: Option<String>,
+    /// OpenAPI 3 specification (YAML or JSON) whose operations are stubbed after the routes,
+    /// with their examples or values generated from their schemas
+    #[serde(default)]
+    openapi: Option<String>,
+    /// Return a 400 for the requests violating the OpenAPI specification
+    #[serde(default)]
+    validate_requests: bool,
+    /// Directory of the scenario, set by the scenario runner: relative files are resolved against it
+    #[serde(default)]
+    scenario_dir: Option<String>,
+    /// Specification loaded with the routes, kept to validate the requests
+    #[serde(skip)]
+    spec: Option<Arc<Spec>>,
+    output_file: Option<String>,
+    /// Stateful REST collections, served when no route matches
+    #[serde(default)]
+    resources: Vec<Resource>,
+    /// File holding the latest items of the resources
+    #[serde(default)]
+    resources_file: Option<String>,
+    /// JSON lines file recording every request received
+    #[serde(default)]
+    journal_file: Option<String>,
+    /// Seed of the random generator of the faults. A random seed is used (and logged) if not specified.
+    #[serde(default)]
+    seed: Option<u64>,
+    /// Address of the admin API, managing the stubs at runtime
+    #[serde(default)]
+    admin_addr: Option<String>,
+}
+
+#[derive(Default, Debug ,Serialize, Deserialize, Clone, PartialEq)]
+pub(super) struct Route {
+    pub(super) path: String,
+    pub(super) method: String,
+    /// Query parameters the request must match.
+    /// A parameter repeated in the query string (`?tag=a&tag=b`) is matched with its last value only.
+    #[serde(default)]
+    query: BTreeMap<String, Matcher>,
+    /// Headers the request must match
+    #[serde(default)]
+    headers: BTreeMap<String, Matcher>,
+    /// Body the request must match, any body matching if not set
+    #[serde(default)]
+    body: Option<RouteBody>,
+    #[serde(flatten)]
+    response: StubResponse,
+    /// Responses returned in turn on each call, instead of the single response
+    #[serde(default)]
+    responses: Vec<StubResponse>,
+    /// What to return once the sequence of responses is exhausted
+    #[serde(default)]
+    sequence: SequenceMode,
+    /// Whether the calls are counted for the route or for each distinct request
+    #[serde(default)]
+    counter: CounterScope,
+}
+
+#[derive(Default, Debug ,Serialize, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "snake_case")]
+enum SequenceMode {
+    #[default]
+    RepeatLast,
+    Cycle,
 }
 
 #[derive(Default, Debug ,Serialize, Deserialize, Clone, PartialEq)]
-struct Route {
-    path: String,
-    method: String,
-    status: u16,
-    body: Option<String>,
-    result: Option<String>,
+#[serde(rename_all = "snake_case")]
+enum CounterScope {
+    #[default]
+    Route,
+    /// Requests with the same method, URI & body
+    Request,
+}
+
+/// Matcher of the request body. A plain string is the JSON payload the request must be equal to.
+#[derive(Debug ,Serialize, Deserialize, Clone, PartialEq)]
+#[serde(untagged)]
+enum RouteBody {
+    Json(String),
+    Matcher(BodyMatcher),
+}
+
+impl RouteBody {
+    fn validate(&self) -> Result<()> {
+        match self {
+            RouteBody::Json(s) => serde_json::from_str::<jsonValue>(s)
+                .map(|_| ())
+                .map_err(|e| anyhow!("Invalid JSON body {}: {}", s, e)),
+            // Regexes are checked when the matcher is deserialized
+            RouteBody::Matcher(_) => Ok(()),
+        }
+    }
+
+    fn matches(&self, raw: &[u8]) -> bool {
+        match self {
+            RouteBody::Json(s) => serde_json::from_str(s).is_ok_and(|v| BodyMatcher::Equals(v).matches_raw(raw)),
+            RouteBody::Matcher(m) => m.matches_raw(raw),
+        }
+    }
+
+    /// Explain why a payload does not match, None if it matches.
+    fn explain(&self, raw: &[u8]) -> Option<String> {
+        match self {
+            RouteBody::Json(s) => match serde_json::from_str(s) {
+                Ok(v) => BodyMatcher::Equals(v).explain(raw),
+                Err(_) => Some(format!("differed from the invalid JSON {}", s)),
+            },
+            RouteBody::Matcher(m) => m.explain(raw),
+        }
+    }
+}
+
+impl Route {
+    fn resolve_paths(&mut self, dir: &Path) {
+        self.response.resolve_paths(dir);
+
+        for res in self.responses.iter_mut() {
+            res.resolve_paths(dir);
+        }
+    }
+
+    pub(super) fn validate(&self) -> Result<()> {
+        validate_pattern(&self.path)?;
+
+        if !METHODS.iter().any(|m| self.method.eq_ignore_ascii_case(m)) {
+            return Err(anyhow!("HTTP Method not supported: {}", self.method));
+        }
+
+        if let Some(b) = &self.body {
+            b.validate()?;
+        }
+
+        self.response.validate()?;
+
+        for res in self.responses.iter() {
+            res.validate()?;
+        }
+
+        Ok(())
+    }
+
+    /// Response to return for the n-th call (from 0).
+    fn response(&self, call: usize) -> &StubResponse {
+        if self.responses.is_empty() {
+            return &self.response;
+        }
+
+        let i = match self.sequence {
+            SequenceMode::RepeatLast => call.min(self.responses.len() - 1),
+            SequenceMode::Cycle => call % self.responses.len(),
+        };
+
+        &self.responses[i]
+    }
+
+    /// Check the query parameters & headers of a request.
+    fn matches(&self, query: &BTreeMap<String, String>, headers: &HeaderMap) -> bool {
+        self.query.iter().all(|(name, m)| m.matches(query.get(name).map(|v| v.as_str())))
+            && self.headers.iter().all(|(name, m)| m.matches(headers.get(name.as_str()).and_then(|v| v.to_str().ok())))
+    }
+}
+
+/// Load the routes of a YAML or JSON file, their result files being relative to the file.
+fn load_routes_file(file: &Path) -> Result<Vec<Route>> {
+    let text = fs::read_to_string(file).map_err(|e| anyhow!("Reading routes {}: {}", file.display(), e))?;
+    let mut routes: Vec<Route> = serde_yaml::from_str(&text).map_err(|e| anyhow!("Parsing routes {}: {}", file.display(), e))?;
+
+    let dir = file.parent().unwrap_or_else(|| Path::new(""));
+    for r in routes.iter_mut() {
+        r.resolve_paths(dir);
+    }
+
+    Ok(routes)
+}
+
+impl HttpServer {
+    fn scenario_path(&self, file: &str) -> PathBuf {
+        match &self.scenario_dir {
+            Some(d) => Path::new(d).join(file),
+            None => PathBuf::from(file),
+        }
+    }
+
+    /// Resolve the relative result files of the inline routes, then append the routes of routes_file & routes_dir,
+    /// and the operations of the OpenAPI specification: explicit routes override them.
+    fn load_routes(&mut self) -> Result<()> {
+        if let Some(d) = &self.scenario_dir {
+            for r in self.routes.iter_mut() {
+                r.resolve_paths(Path::new(d));
+            }
+        }
+
+        if let Some(f) = &self.routes_file {
+            let routes = load_routes_file(&self.scenario_path(f))?;
+            info!("{} routes loaded from {}", routes.len(), f);
+
+            self.routes.extend(routes);
+        }
+
+        if let Some(d) = &self.routes_dir {
+            let dir = self.scenario_path(d);
+
+            let mut files: Vec<PathBuf> = fs::read_dir(&dir)
+                .map_err(|e| anyhow!("Reading routes_dir {}: {}", dir.display(), e))?
+                .filter_map(|e| e.ok().map(|e| e.path()))
+                .filter(|p| p.extension().is_some_and(|x| x == "yaml" || x == "yml" || x == "json"))
+                .collect();
+            files.sort();
+
+            for f in files.iter() {
+                let routes = load_routes_file(f)?;
+                info!("{} routes loaded from {}", routes.len(), f.display());
+
+                self.routes.extend(routes);
+            }
+        }
+
+        if let Some(f) = &self.openapi {
+            let spec = Spec::load(&self.scenario_path(f))?;
+            let stubs = spec.stubs();
+            info!("{} operations stubbed from {}", stubs.len(), f);
+
+            for (method, path, response) in stubs.into_iter() {
+                self.routes.push(Route { path, method, response, ..Default::default() });
+            }
+
+            self.spec = Some(Arc::new(spec));
+        }
+
+        Ok(())
+    }
 }
 
 #[async_trait]
@@ -51,6 +304,17 @@ impl FakerMod for HttpServer {
     type Future = Pin<Box<dyn Future<Output = Result<(), Error>> + Send>>;
 
     fn validate_params(&self) -> Result<()> {
+        for r in self.routes.iter() {
+            r.validate()?;
+        }
+
+        for r in self.resources.iter() {
+            r.validate()?;
+        }
+
+        if self.validate_requests && self.openapi.is_none() {
+            return Err(anyhow!("validate_requests needs an openapi specification"));
+        }
 
         Ok(())
     }
@@ -58,33 +322,47 @@ impl FakerMod for HttpServer {
     fn func(&self, _tx: Sender<bool>, _rx: Receiver<bool>) -> Self::Future {
         let _ =  env_logger::try_init();
 
-        let shared_routes = Arc::new(self.routes.clone());
-        let shared_memstore = Arc::new(Mutex::new(Map::new()));
-
-        let mut output = String::new();
-        if let Some(o) = &self.output_file {
-            output = o.clone();
+        let resources = ResourceStore::new(&self.resources, self.resources_file.clone());
+        if let Err(e) = resources.dump() {
+            return Box::pin(future::err(e));
         }
-        let shared_output_file = Arc::new(output);
+
+        // Only the admin API reads the entries back
+        let journal = match Journal::new(self.journal_file.as_deref(), self.admin_addr.is_some()) {
+            Ok(j) => j,
+            Err(e) => return Box::pin(future::err(e)),
+        };
+
+        let seed = self.seed.unwrap_or_else(rand::random::<u64>);
+        info!("HTTP server faults seeded with {}", seed);
+
+        let state = Arc::new(ServerState {
+            host_addr: self.host_addr.clone(),
+            stubs: RwLock::new(Stubs::new(&self.routes)),
+            initial_routes: self.routes.clone(),
+            output_file: self.output_file.clone().unwrap_or_default(),
+            memstore: Mutex::new(Map::new()),
+            calls: Mutex::new(HashMap::new()),
+            rng: Mutex::new(StdRng::seed_from_u64(seed)),
+            journal,
+            resources: Mutex::new(resources),
+            spec: self.spec.clone().filter(|_| self.validate_requests),
+        });
 
         // Initialize tracing
         //tracing_subscriber::fmt::init();
 
-        // Build our application with a route
-        let mut app = Router::new();
-        for r in self.routes.iter() {
-            match r.method.as_str() {
-                "GET" => app = app.route(r.path.as_str(), get(get_handler)),
-                "POST" => app = app.route(r.path.as_str(), post(post_handler)),
-                "DELETE" => app = app.route(r.path.as_str(), delete(delete_handler)),
-                "PUT" => app = app.route(r.path.as_str(), put(put_handler)),
-                _ => return Box::pin(future::err(anyhow!("HTTP Method not supported!"))),
-            };
+        let admin_addr = match self.admin_addr.as_ref().map(|a| a.parse::<SocketAddrV4>()).transpose() {
+            Ok(a) => a,
+            Err(e) => return Box::pin(future::err(anyhow!(e))),
+        };
+        let admin_app = admin::router(state.clone());
 
-        }
-        app = app.layer(Extension(shared_routes))
-            .layer(Extension(shared_output_file))
-            .layer(Extension(shared_memstore));
+        // Build our application: the stubs are selected by the handler, which receives
+        // every request (a `/` route would conflict with a `/*path` one)
+        let app = Router::new()
+            .fallback(any(handler))
+            .layer(Extension(state));
 
         // run our app with hyper
         // `axum::Server` is a re-export of `hyper::Server`
@@ -95,9 +373,22 @@ impl FakerMod for HttpServer {
 
         info!("Listening on {}", addr);
         Box::pin(async move {
-            if let Err(e) = axum::Server::bind(&SocketAddr::V4(addr))
-                .serve(app.into_make_service())
-                .await {
+            let server = axum::Server::bind(&SocketAddr::V4(addr))
+                .serve(Shared::new(CloseConnections(app)));
+
+            let result = match admin_addr {
+                Some(a) => {
+                    info!("Admin API listening on {}", a);
+
+                    let admin = axum::Server::bind(&SocketAddr::V4(a))
+                        .serve(admin_app.into_make_service());
+
+                    future::try_join(server, admin).await.map(|_| ())
+                },
+                None => server.await,
+            };
+
+            if let Err(e) = result {
                 return Err(anyhow!(e));
             }
 
@@ -106,143 +397,270 @@ impl FakerMod for HttpServer {
     }
 }
 
-async fn get_handler(
-    // this argument tells axum to parse the request body
-    // as JSON into a `CreateUser` type
-    matched_path: MatchedPath,
-    Extension(routes): Extension<Arc<Vec<Route>>>,
-    Extension(output_file): Extension<Arc<String>>,
-    Extension(memstore): Extension<Arc<Mutex<Map<String, jsonValue>>>>,
-) -> impl IntoResponse {
+/// State shared by the requests.
+pub(super) struct ServerState {
+    /// Address the server listens on, identifying its unmatched requests & its template counters
+    pub(super) host_addr: String,
+    pub(super) stubs: RwLock<Stubs>,
+    /// Routes of the configuration, restored by a reset
+    pub(super) initial_routes: Vec<Route>,
+    output_file: String,
+    /// Results returned, by request path
+    memstore: Mutex<Map<String, jsonValue>>,
+    /// Number of calls by route id & request (empty when counted per route)
+    pub(super) calls: Mutex<HashMap<(usize, String), usize>>,
+    /// Random generator of the faults
+    rng: Mutex<StdRng>,
+    pub(super) journal: Journal,
+    resources: Mutex<ResourceStore>,
+    /// Specification the requests are validated against
+    spec: Option<Arc<Spec>>,
+}
 
-    let path = matched_path.as_str();
-    debug!("GET Path: {}", path);
+/// Routes with their ids: the routes of the configuration get their index, routes added at runtime the next ids.
+pub(super) struct Stubs {
+    pub(super) routes: Vec<(usize, Route)>,
+    next_id: usize,
+}
 
-    let route = routes.iter().find(|r| r.path == path && r.method == "GET");
-    if let Some(r) = route.clone() {
-        if let Some(res) = &r.result {
-            let v_res: jsonValue = serde_json::from_str(&res).unwrap();
-            let mut store = memstore.lock().unwrap();
+impl Stubs {
+    pub(super) fn new(routes: &[Route]) -> Self {
+        Stubs {
+            routes: routes.iter().cloned().enumerate().collect(),
+            next_id: routes.len(),
+        }
+    }
 
-            store.insert(matched_path.as_str().to_string(), v_res.clone());
+    /// Add a route, tried after the existing ones.
+    pub(super) fn add(&mut self, route: Route) -> usize {
+        let id = self.next_id;
+        self.next_id += 1;
 
-            write_output_file(output_file, store.clone());
+        self.routes.push((id, route));
 
-            return (StatusCode::from_u16(r.status).unwrap(), Json(v_res));
-        }
+        id
     }
 
-    (StatusCode::NOT_FOUND, Json(jsonValue::Null))
+    pub(super) fn remove(&mut self, id: usize) -> bool {
+        let len = self.routes.len();
+        self.routes.retain(|(i, _)| *i != id);
+
+        self.routes.len() != len
+    }
 }
 
-async fn post_handler(
-    // this argument tells axum to parse the request body
-    // as JSON into a `CreateUser` type
-    matched_path: MatchedPath,
-    Json(payload): Json<jsonValue>,
-    Extension(routes): Extension<Arc<Vec<Route>>>,
-    Extension(output_file): Extension<Arc<String>>,
-    Extension(memstore): Extension<Arc<Mutex<Map<String, jsonValue>>>>,
-) -> impl IntoResponse {
+/// Find the first route matching the request method, path & the predicate, along with its id and the captured path parameters.
+///
+/// Routes are tried in the order of declaration, so several stubs of a path can be selected by their matchers.
+fn find_route<'a>(routes: &'a [(usize, Route)], method: &Method, path: &str, predicate: impl Fn(&Route) -> bool) -> Option<(usize, &'a Route, Map<String, jsonValue>)> {
+    routes.iter()
+        .filter(|(_, r)| r.method.eq_ignore_ascii_case(ANY_METHOD) || r.method.eq_ignore_ascii_case(method.as_str()))
+        .find_map(|(i, r)| match_path(&r.path, path).filter(|_| predicate(r)).map(|params| (*i, r, params)))
+}
 
-    let path = matched_path.as_str();
-    debug!("POST Path: {}", path);
+/// Explain why no route matched a request, with the differences to the closest route:
+/// the one with the fewest differences, a route of the same path being always closer.
+fn closest_route(routes: &[(usize, Route)], method: &Method, path: &str, query: &BTreeMap<String, String>, headers: &HeaderMap, body: &[u8]) -> Option<String> {
+    routes.iter()
+        .map(|(id, r)| {
+            let path_matched = match_path(&r.path, path).is_some();
+            let mut differences = vec![if path_matched { "path matched".to_string() } else { "path differed".to_string() }];
 
-    let route = routes.iter().find(|r| r.path == path && r.method == "POST" && serde_json::from_str::<jsonValue>(&r.body.as_ref().unwrap()).unwrap() == payload);
-    if let Some(r) = route.clone() {
-        if let Some(res) = &r.result {
-            let v_res: jsonValue = serde_json::from_str(&res).unwrap();
-            let mut store = memstore.lock().unwrap();
+            if !r.method.eq_ignore_ascii_case(ANY_METHOD) && !r.method.eq_ignore_ascii_case(method.as_str()) {
+                differences.push(format!("method differed (expected {})", r.method));
+            }
 
-            store.insert(matched_path.as_str().to_string(), v_res.clone());
+            for (name, m) in r.query.iter().filter(|(name, m)| !m.matches(query.get(*name).map(|v| v.as_str()))) {
+                differences.push(format!("query `{}` differed (expected {:?})", name, m));
+            }
 
-            write_output_file(output_file, store.clone());
+            for (name, m) in r.headers.iter().filter(|(name, m)| !m.matches(headers.get(name.as_str()).and_then(|v| v.to_str().ok()))) {
+                differences.push(format!("header `{}` differed (expected {:?})", name, m));
+            }
 
-            return (StatusCode::from_u16(r.status).unwrap(), Json(v_res));
-        }
-    }
+            if let Some(e) = r.body.as_ref().and_then(|b| b.explain(body)) {
+                differences.push(format!("body {}", e));
+            }
+
+            let distance = differences.len() + if path_matched { 0 } else { 100 };
+
+            (distance, format!("closest route {} {} (id {}): {}", r.method, r.path, id, differences.join(", ")))
+        })
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, explanation)| explanation)
+}
 
-    (StatusCode::NOT_FOUND, Json(jsonValue::Null))
+/// Request data available in the response templates: `{{params.id}}`, `{{query.page}}`,
+/// `{{headers.authorization}}`, `{{body.order.id}}`...
+fn request_context(method: &Method, path: &str, params: Map<String, jsonValue>, query: &BTreeMap<String, String>, headers: &HeaderMap, body: &[u8]) -> jsonValue {
+    // Header names are lowercase
+    let headers: Map<String, jsonValue> = headers.iter()
+        .filter_map(|(k, v)| v.to_str().ok().map(|v| (k.to_string(), jsonValue::String(v.to_string()))))
+        .collect();
+
+    json!({
+        "method": method.as_str(),
+        "path": path,
+        "params": params,
+        "query": query,
+        "headers": headers,
+        "body": body_value(body),
+    })
 }
 
-async fn delete_handler(
-    // this argument tells axum to parse the request body
-    // as JSON into a `CreateUser` type
-    matched_path: MatchedPath,
-    Extension(routes): Extension<Arc<Vec<Route>>>,
-    Extension(output_file): Extension<Arc<String>>,
-    Extension(memstore): Extension<Arc<Mutex<Map<String, jsonValue>>>>,
-) -> impl IntoResponse {
+fn error_response(e: Error) -> Response {
+    error!("http-server: {}", e);
+
+    (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({"error": e.to_string()}))).into_response()
+}
 
-    let path = matched_path.as_str();
-    debug!("DELETE Path: {}", path);
+async fn handler(
+    method: Method,
+    uri: Uri,
+    // One value per parameter: the last one wins when a parameter is repeated,
+    // for the matching as well as the journal & the templates
+    Query(query): Query<BTreeMap<String, String>>,
+    headers: HeaderMap,
+    Extension(state): Extension<Arc<ServerState>>,
+    body: Bytes,
+) -> Response {
 
-    let route = routes.iter().find(|r| r.path == path && r.method == "DELETE");
-    if let Some(r) = route.clone() {
-        let mut store = memstore.lock().unwrap();
+    let mut entry = JournalEntry::new(&method, &uri, &query, &headers, &body);
+    entry.sequence = state.journal.next_sequence();
 
-        store.remove(matched_path.as_str());
+    let (response, matched, closest_match) = serve(&state, &method, &uri, &query, &headers, &body).await;
 
-        write_output_file(output_file, store.clone());
+    entry.matched = matched;
+    entry.closest_match = closest_match;
+    entry.status = (!fault::closes_connection(&response)).then(|| response.status().as_u16());
 
-        if let Some(res) = &r.result {
-            let v_res: jsonValue = serde_json::from_str(&res).unwrap();
+    state.journal.record(&entry);
 
-            return (StatusCode::from_u16(r.status).unwrap(), Json(v_res));
-        }
+    response
+}
 
-        return (StatusCode::from_u16(r.status).unwrap(), Json(jsonValue::Null))
+/// Serve a request, returning the response, the route or resource matched, and if none matched, the explanation of the closest route.
+async fn serve(state: &ServerState, method: &Method, uri: &Uri, query: &BTreeMap<String, String>, headers: &HeaderMap, body: &[u8]) -> (Response, Option<MatchedRoute>, Option<String>) {
+    let path = uri.path();
+    debug!("{} Path: {}", method, path);
+
+    if let Some(spec) = &state.spec {
+        if let Err(violations) = spec.validate_request(method, path, query, headers, body) {
+            warn!("{} {} violates the OpenAPI specification: {:?}", method, path, violations);
+
+            let body = json!({"error": "the request violates the OpenAPI specification", "violations": violations});
+            return ((StatusCode::BAD_REQUEST, Json(body)).into_response(), None, None);
+        }
     }
 
-    (StatusCode::NOT_FOUND, Json(jsonValue::Null))
-}
+    // The route is cloned: the stubs can be modified by the admin API meanwhile
+    let route = find_route(&state.stubs.read().unwrap().routes, method, path, |r| {
+        r.matches(query, headers) && r.body.as_ref().is_none_or(|b| b.matches(body))
+    }).map(|(id, r, params)| (id, r.clone(), params));
+
+    let (id, r, params) = match route {
+        Some(r) => r,
+        None => {
+            if let Some((resource, response)) = state.resources.lock().unwrap().handle(method, path, body) {
+                let matched = MatchedRoute { id: None, method: method.to_string(), path: resource };
+                return (response, Some(matched), None);
+            }
 
-async fn put_handler(
-    // this argument tells axum to parse the request body
-    // as JSON into a `CreateUser` type
-    matched_path: MatchedPath,
-    Json(payload): Json<jsonValue>,
-    Extension(routes): Extension<Arc<Vec<Route>>>,
-    Extension(output_file): Extension<Arc<String>>,
-    Extension(memstore): Extension<Arc<Mutex<Map<String, jsonValue>>>>,
-) -> impl IntoResponse {
+            let closest = closest_route(&state.stubs.read().unwrap().routes, method, path, query, headers, body)
+                .unwrap_or_else(|| "no route defined".to_string());
 
-    let path = matched_path.as_str();
-    debug!("PUT Path: {}", path);
+            warn!("No route matched {} {}: {}", method, uri, closest);
+            unmatched::record(&state.host_addr, format!("{} {}: {}", method, uri, closest));
 
-    let route = routes.iter().find(|r| r.path == path && r.method == "PUT" && serde_json::from_str::<jsonValue>(&r.body.as_ref().unwrap()).unwrap() == payload);
-    if let Some(r) = route.clone() {
-        if let Some(res) = &r.result {
-            let v_res: jsonValue = serde_json::from_str(&res).unwrap();
-            let mut store = memstore.lock().unwrap();
+            return ((StatusCode::NOT_FOUND, Json(jsonValue::Null)).into_response(), None, Some(closest));
+        },
+    };
 
-            store.insert(matched_path.as_str().to_string(), v_res.clone());
+    let matched = Some(MatchedRoute { id: Some(id), method: r.method.clone(), path: r.path.clone() });
 
-            write_output_file(output_file, store.clone());
+    let mut context = request_context(method, path, params, query, headers, body);
+    // The admin API resets the counters of this server only
+    template::scope_counters(&mut context, &state.host_addr);
 
-            return (StatusCode::from_u16(r.status).unwrap(), Json(v_res));
+    let call = {
+        let key = match r.counter {
+            CounterScope::Route => String::new(),
+            CounterScope::Request => format!("{} {} {}", method, uri, String::from_utf8_lossy(body)),
+        };
+
+        let mut calls = state.calls.lock().unwrap();
+        let count = calls.entry((id, key)).or_insert(0);
+        *count += 1;
+
+        *count - 1
+    };
+
+    let stub = r.response(call);
+
+    let response = match stub.render(&context) {
+        Ok(response) => response,
+        Err(e) => return (error_response(e), matched, None),
+    };
+
+    // The store keeps the last result of each path, until it is deleted
+    if *method == Method::DELETE {
+        let mut store = state.memstore.lock().unwrap();
+
+        store.remove(path);
+
+        if let Err(e) = write_output_file(&state.output_file, &store) {
+            error!("http-server: writing the output file {}: {}", state.output_file, e);
+        }
+    } else if let (true, Some(v)) = ([Method::GET, Method::POST, Method::PUT, Method::PATCH].contains(method), &response.value) {
+        let mut store = state.memstore.lock().unwrap();
+
+        store.insert(path.to_string(), v.clone());
+
+        if let Err(e) = write_output_file(&state.output_file, &store) {
+            error!("http-server: writing the output file {}: {}", state.output_file, e);
         }
     }
 
-    (StatusCode::NOT_FOUND, Json(jsonValue::Null))
+    let (delay, failure) = match &stub.fault {
+        Some(f) => f.plan(&mut state.rng.lock().unwrap()),
+        None => (Duration::ZERO, None),
+    };
+
+    if !delay.is_zero() {
+        debug!("Fault: delaying the response of {} {} by {:?}", method, path, delay);
+        sleep(delay).await;
+    }
+
+    let response = match failure {
+        Some(f) => {
+            info!("Fault: {:?} on {} {}", f, method, path);
+            f.response(response)
+        },
+        None => response.into_response(),
+    };
+
+    (response, matched, None)
 }
 
-fn write_output_file(output_file: Arc<String>, store: Map<String, jsonValue>) {
+fn write_output_file(output_file: &str, store: &Map<String, jsonValue>) -> Result<()> {
     if !output_file.is_empty() {
-        let mut f = File::create(output_file.as_ref()).unwrap();
-        let text = serde_json::to_string_pretty(&store).unwrap();
+        let mut f = File::create(output_file)?;
+        let text = serde_json::to_string_pretty(store)?;
 
-        f.write_all(text.as_bytes()).unwrap();
-        f.write_all(b"\n").unwrap();
+        f.write_all(text.as_bytes())?;
+        f.write_all(b"\n")?;
     }
+
+    Ok(())
 }
 
 fn func(params: Mapping, tx: Sender<bool>, rx: Receiver<bool>) -> Pin<Box<dyn Future<Output = Result<(), Error>> + Send>> {
     Box::pin(async move {
         let v_params = yamlValue::Mapping(params);
 
-        let server: HttpServer = serde_yaml::from_value(v_params)?;
+        let mut server: HttpServer = serde_yaml::from_value(v_params)?;
 
+        server.load_routes()?;
         server.validate_params()?;
         server.func(tx, rx).await?;
 
@@ -260,6 +678,13 @@ mod tests {
     use super::*;
     use tokio::time::{sleep, Duration};
 
+    fn json_body(r: &Route) -> String {
+        match &r.body {
+            Some(RouteBody::Json(b)) => b.clone(),
+            _ => String::new(),
+        }
+    }
+
     #[tokio::test]
     async fn httpserver_func() {
         let params: Mapping = serde_yaml::from_str(r#"
@@ -321,20 +746,20 @@ output_file: /tmp/httpserver.json
                     .await
                     .unwrap();
 
-                assert_eq!(r.status, response.status().as_u16());
-                assert_eq!(serde_json::from_str::<jsonValue>(r.result.as_ref().unwrap()).unwrap(), response.json::<jsonValue>().await.unwrap())
+                assert_eq!(r.response.status, response.status().as_u16());
+                assert_eq!(serde_json::from_str::<jsonValue>(r.response.result.as_ref().unwrap()).unwrap(), response.json::<jsonValue>().await.unwrap())
             }
 
             if r.method == "POST" {
                 let response = client.post(format!("http://localhost:3000{}", r.path).as_str())
                     .header(CONTENT_TYPE, "application/json")
-                    .body(r.body.clone().unwrap())
+                    .body(json_body(&r))
                     .send()
                     .await
                     .unwrap();
 
-                assert_eq!(r.status, response.status().as_u16());
-                assert_eq!(serde_json::from_str::<jsonValue>(r.result.as_ref().unwrap()).unwrap(), response.json::<jsonValue>().await.unwrap())
+                assert_eq!(r.response.status, response.status().as_u16());
+                assert_eq!(serde_json::from_str::<jsonValue>(r.response.result.as_ref().unwrap()).unwrap(), response.json::<jsonValue>().await.unwrap())
             }
 
             if r.method == "DELETE" {
@@ -344,20 +769,634 @@ output_file: /tmp/httpserver.json
                     .await
                     .unwrap();
 
-                assert_eq!(r.status, response.status().as_u16());
+                assert_eq!(r.response.status, response.status().as_u16());
             }
 
             if r.method == "PUT" {
                 let response = client.put(format!("http://localhost:3000{}", r.path).as_str())
                     .header(CONTENT_TYPE, "application/json")
-                    .body(r.body.clone().unwrap())
+                    .body(json_body(&r))
                     .send()
                     .await
                     .unwrap();
 
-                assert_eq!(r.status, response.status().as_u16());
-                assert_eq!(serde_json::from_str::<jsonValue>(r.result.as_ref().unwrap()).unwrap(), response.json::<jsonValue>().await.unwrap())
+                assert_eq!(r.response.status, response.status().as_u16());
+                assert_eq!(serde_json::from_str::<jsonValue>(r.response.result.as_ref().unwrap()).unwrap(), response.json::<jsonValue>().await.unwrap())
             }
         }
     }
+
+    #[tokio::test]
+    async fn httpserver_path_params() {
+        let server: HttpServer = serde_yaml::from_str(r#"
+host_addr: "127.0.0.1:3001"
+routes:
+- path: /users/:id
+  method: "GET"
+  status: 200
+  result: |
+    {
+      "id": "{{params.id}}"
+    }
+- path: /files/*file
+  method: "GET"
+  status: 200
+  result: |
+    {
+      "file": "{{params.file}}"
+    }
+output_file: /tmp/httpserver_path_params.json
+"#).unwrap();
+        server.validate_params().unwrap();
+
+        let (tx, rx) = tokio::sync::broadcast::channel(16);
+        tokio::spawn(async move{
+            let _ = server.func(tx, rx).await;
+        });
+
+        sleep(Duration::from_millis(100)).await;
+
+        let client = reqwest::Client::new();
+
+        for (path, expected) in [
+            ("/users/1", serde_json::json!({"id": "1"})),
+            ("/users/2", serde_json::json!({"id": "2"})),
+            ("/files/a/b.txt", serde_json::json!({"file": "a/b.txt"})),
+        ] {
+            let response = client.get(format!("http://localhost:3001{}", path).as_str())
+                .send()
+                .await
+                .unwrap();
+
+            assert_eq!(200, response.status().as_u16());
+            assert_eq!(expected, response.json::<jsonValue>().await.unwrap());
+        }
+
+        // The store is keyed by the concrete path
+        let store: jsonValue = serde_json::from_reader(File::open("/tmp/httpserver_path_params.json").unwrap()).unwrap();
+        assert_eq!(serde_json::json!({"id": "2"}), store["/users/2"]);
+    }
+
+    #[tokio::test]
+    async fn httpserver_query_headers() {
+        let server: HttpServer = serde_yaml::from_str(r#"
+host_addr: "127.0.0.1:3002"
+routes:
+- path: /orders
+  method: "GET"
+  query:
+    status:
+      equals: active
+  headers:
+    authorization:
+      regex: "^Bearer .+$"
+  status: 200
+  result: '{"orders": "active"}'
+- path: /orders
+  method: "GET"
+  query:
+    status:
+      equals: deleted
+  status: 200
+  result: '{"orders": "deleted"}'
+- path: /orders
+  method: "GET"
+  query:
+    status:
+      absent: true
+  status: 200
+  result: '{"orders": "all"}'
+"#).unwrap();
+        server.validate_params().unwrap();
+
+        let (tx, rx) = tokio::sync::broadcast::channel(16);
+        tokio::spawn(async move{
+            let _ = server.func(tx, rx).await;
+        });
+
+        sleep(Duration::from_millis(100)).await;
+
+        let client = reqwest::Client::new();
+
+        for (query, auth, status, expected) in [
+            ("?status=active", Some("Bearer t1"), 200, serde_json::json!({"orders": "active"})),
+            ("?status=active", None, 404, jsonValue::Null),
+            ("?status=deleted", None, 200, serde_json::json!({"orders": "deleted"})),
+            // Repeated parameter: the last value is matched
+            ("?status=active&status=deleted", None, 200, serde_json::json!({"orders": "deleted"})),
+            ("", None, 200, serde_json::json!({"orders": "all"})),
+        ] {
+            let mut request = client.get(format!("http://localhost:3002/orders{}", query).as_str());
+            if let Some(a) = auth {
+                request = request.header("Authorization", a);
+            }
+
+            let response = request.send().await.unwrap();
+
+            assert_eq!(status, response.status().as_u16());
+            assert_eq!(expected, response.json::<jsonValue>().await.unwrap());
+        }
+    }
+
+    #[tokio::test]
+    async fn httpserver_body_matchers() {
+        let server: HttpServer = serde_yaml::from_str(r#"
+host_addr: "127.0.0.1:3003"
+routes:
+- path: /orders
+  method: "POST"
+  body:
+    contains:
+      type: express
+  status: 201
+  result: '{"delivery": "express"}'
+- path: /orders
+  method: "POST"
+  body:
+    json_path:
+      $.items.0.sku:
+        regex: "^A-"
+  status: 201
+  result: '{"delivery": "A"}'
+- path: /login
+  method: "POST"
+  body:
+    form:
+      user:
+        equals: bob
+  status: 200
+  result: '{"user": "bob"}'
+- path: /orders
+  method: "POST"
+  status: 400
+  result: '{"error": "unknown order"}'
+"#).unwrap();
+        server.validate_params().unwrap();
+
+        let (tx, rx) = tokio::sync::broadcast::channel(16);
+        tokio::spawn(async move{
+            let _ = server.func(tx, rx).await;
+        });
+
+        sleep(Duration::from_millis(100)).await;
+
+        let client = reqwest::Client::new();
+
+        for (path, body, status, expected) in [
+            ("/orders", r#"{"type": "express", "items": []}"#, 201, json!({"delivery": "express"})),
+            ("/orders", r#"{"type": "standard", "items": [{"sku": "A-1"}]}"#, 201, json!({"delivery": "A"})),
+            ("/orders", "not json", 400, json!({"error": "unknown order"})),
+            ("/login", "user=bob&password=secret", 200, json!({"user": "bob"})),
+            ("/login", "user=alice", 404, jsonValue::Null),
+        ] {
+            let response = client.post(format!("http://localhost:3003{}", path).as_str())
+                .body(body)
+                .send()
+                .await
+                .unwrap();
+
+            assert_eq!(status, response.status().as_u16());
+            assert_eq!(expected, response.json::<jsonValue>().await.unwrap());
+        }
+    }
+
+    #[tokio::test]
+    async fn httpserver_methods() {
+        let server: HttpServer = serde_yaml::from_str(r#"
+host_addr: "127.0.0.1:3004"
+routes:
+- path: /users/:id
+  method: "PATCH"
+  status: 200
+  result: '{"patched": "{{params.id}}"}'
+- path: /users/:id
+  method: "OPTIONS"
+  status: 204
+- path: /health
+  method: "ANY"
+  status: 200
+  result: '{"status": "up"}'
+"#).unwrap();
+        server.validate_params().unwrap();
+
+        let (tx, rx) = tokio::sync::broadcast::channel(16);
+        tokio::spawn(async move{
+            let _ = server.func(tx, rx).await;
+        });
+
+        sleep(Duration::from_millis(100)).await;
+
+        let client = reqwest::Client::new();
+
+        let response = client.patch("http://localhost:3004/users/1").send().await.unwrap();
+        assert_eq!(200, response.status().as_u16());
+        assert_eq!(json!({"patched": "1"}), response.json::<jsonValue>().await.unwrap());
+
+        let response = client.request(reqwest::Method::OPTIONS, "http://localhost:3004/users/1").send().await.unwrap();
+        assert_eq!(204, response.status().as_u16());
+
+        for method in [reqwest::Method::GET, reqwest::Method::POST, reqwest::Method::DELETE] {
+            let response = client.request(method, "http://localhost:3004/health").send().await.unwrap();
+            assert_eq!(200, response.status().as_u16());
+        }
+
+        let response = client.get("http://localhost:3004/users/1").send().await.unwrap();
+        assert_eq!(404, response.status().as_u16());
+
+        let invalid: HttpServer = serde_yaml::from_str("{host_addr: '127.0.0.1:3004', routes: [{path: /, method: FETCH, status: 200}]}").unwrap();
+        assert!(invalid.validate_params().is_err());
+    }
+
+    #[tokio::test]
+    async fn httpserver_templates() {
+        let server: HttpServer = serde_yaml::from_str(r#"
+host_addr: "127.0.0.1:3005"
+routes:
+- path: /orders/:id
+  method: "POST"
+  status: 201
+  result: |
+    {
+      "id": "{{params.id}}",
+      "page": "{{query.page}}",
+      "tenant": "{{headers.[x-tenant]}}",
+      "items": {{json body.items}},
+      "uuid": "{{uuid}}",
+      "created_at": {{now format="millis"}}
+    }
+"#).unwrap();
+        server.validate_params().unwrap();
+
+        let (tx, rx) = tokio::sync::broadcast::channel(16);
+        tokio::spawn(async move{
+            let _ = server.func(tx, rx).await;
+        });
+
+        sleep(Duration::from_millis(100)).await;
+
+        let client = reqwest::Client::new();
+
+        let response = client.post("http://localhost:3005/orders/o1?page=2")
+            .header("X-Tenant", "acme")
+            .body(r#"{"items": [{"sku": "a"}]}"#)
+            .send()
+            .await
+            .unwrap();
+
+        assert_eq!(201, response.status().as_u16());
+
+        let v = response.json::<jsonValue>().await.unwrap();
+        assert_eq!(json!("o1"), v["id"]);
+        assert_eq!(json!("2"), v["page"]);
+        assert_eq!(json!("acme"), v["tenant"]);
+        assert_eq!(json!([{"sku": "a"}]), v["items"]);
+        assert_eq!(36, v["uuid"].as_str().unwrap().len());
+        assert!(v["created_at"].is_i64());
+    }
+
+    #[tokio::test]
+    async fn httpserver_sequences() {
+        let server: HttpServer = serde_yaml::from_str(r#"
+host_addr: "127.0.0.1:3006"
+routes:
+- path: /payments
+  method: "POST"
+  responses:
+  - status: 503
+  - status: 503
+  - status: 200
+    result: '{"paid": true}'
+- path: /flaky
+  method: "GET"
+  sequence: cycle
+  responses:
+  - status: 200
+  - status: 500
+- path: /items/:id
+  method: "GET"
+  counter: request
+  responses:
+  - status: 404
+  - status: 200
+"#).unwrap();
+        server.validate_params().unwrap();
+
+        let (tx, rx) = tokio::sync::broadcast::channel(16);
+        tokio::spawn(async move{
+            let _ = server.func(tx, rx).await;
+        });
+
+        sleep(Duration::from_millis(100)).await;
+
+        let client = reqwest::Client::new();
+
+        let mut statuses = Vec::new();
+        for _ in 0..4 {
+            statuses.push(client.post("http://localhost:3006/payments").send().await.unwrap().status().as_u16());
+        }
+        assert_eq!(vec![503, 503, 200, 200], statuses);
+
+        let mut statuses = Vec::new();
+        for _ in 0..3 {
+            statuses.push(client.get("http://localhost:3006/flaky").send().await.unwrap().status().as_u16());
+        }
+        assert_eq!(vec![200, 500, 200], statuses);
+
+        let mut statuses = Vec::new();
+        for path in ["/items/1", "/items/2", "/items/1"] {
+            statuses.push(client.get(format!("http://localhost:3006{}", path)).send().await.unwrap().status().as_u16());
+        }
+        assert_eq!(vec![404, 404, 200], statuses);
+    }
+
+    #[tokio::test]
+    async fn httpserver_faults() {
+        let server: HttpServer = serde_yaml::from_str(r#"
+host_addr: "127.0.0.1:3007"
+seed: 42
+routes:
+- path: /slow
+  method: "GET"
+  result: '{"slow": true}'
+  fault:
+    delay_ms: 200
+- path: /malformed
+  method: "GET"
+  result: '{"message": "hello world"}'
+  fault:
+    failure: malformed_json
+- path: /closed
+  method: "GET"
+  result: '{"message": "hello world"}'
+  fault:
+    failure: close_connection
+- path: /drip
+  method: "GET"
+  result: '{"message": "hello"}'
+  fault:
+    failure:
+      slow_drip:
+        chunk_bytes: 5
+        interval_ms: 20
+"#).unwrap();
+        server.validate_params().unwrap();
+
+        let (tx, rx) = tokio::sync::broadcast::channel(16);
+        tokio::spawn(async move{
+            let _ = server.func(tx, rx).await;
+        });
+
+        sleep(Duration::from_millis(100)).await;
+
+        let client = reqwest::Client::new();
+
+        let start = std::time::Instant::now();
+        let response = client.get("http://localhost:3007/slow").send().await.unwrap();
+        assert_eq!(200, response.status().as_u16());
+        assert!(start.elapsed() >= Duration::from_millis(200));
+
+        let response = client.get("http://localhost:3007/malformed").send().await.unwrap();
+        assert!(serde_json::from_str::<jsonValue>(&response.text().await.unwrap()).is_err());
+
+        // Nothing is written, not even the status line
+        let response = client.get("http://localhost:3007/closed").send().await;
+        assert!(response.is_err());
+
+        let start = std::time::Instant::now();
+        let response = client.get("http://localhost:3007/drip").send().await.unwrap();
+        assert_eq!(json!({"message": "hello"}), response.json::<jsonValue>().await.unwrap());
+        assert!(start.elapsed() >= Duration::from_millis(80));
+    }
+
+    #[test]
+    fn httpserver_write_output_file() {
+        let store = Map::from_iter([("/users/1".to_string(), json!({"id": 1}))]);
+
+        assert!(write_output_file("", &store).is_ok());
+        assert!(write_output_file("/tmp/httpserver_missing_dir/output.json", &store).is_err());
+    }
+
+    #[test]
+    fn httpserver_load_routes() {
+        let dir = Path::new("/tmp/httpserver_routes_test");
+        let _ = fs::remove_dir_all(dir);
+        fs::create_dir_all(dir.join("routes.d/bodies")).unwrap();
+
+        fs::write(dir.join("user.json"), r#"{"id": 1}"#).unwrap();
+        fs::write(dir.join("routes.d/bodies/order.json"), r#"{"id": 2}"#).unwrap();
+        fs::write(dir.join("routes.yaml"), r#"
+- path: /users/:id
+  method: GET
+  result: '{"from": "routes_file"}'
+"#).unwrap();
+        fs::write(dir.join("routes.d/b.json"), r#"[{"path": "/orders/:id", "method": "GET", "result_file": "bodies/order.json"}]"#).unwrap();
+        fs::write(dir.join("routes.d/a.yaml"), r#"
+- path: /health
+  method: GET
+"#).unwrap();
+        fs::write(dir.join("routes.d/notes.txt"), "not a route").unwrap();
+
+        let mut server: HttpServer = serde_yaml::from_str(r#"
+host_addr: "127.0.0.1:3010"
+scenario_dir: /tmp/httpserver_routes_test
+routes_file: routes.yaml
+routes_dir: routes.d
+routes:
+- path: /users/:id
+  method: GET
+  result_file: user.json
+"#).unwrap();
+        server.load_routes().unwrap();
+        server.validate_params().unwrap();
+
+        // Inline routes first, then routes_file, then routes_dir by file name
+        let paths: Vec<&str> = server.routes.iter().map(|r| r.path.as_str()).collect();
+        assert_eq!(vec!["/users/:id", "/users/:id", "/health", "/orders/:id"], paths);
+
+        assert_eq!(Some("/tmp/httpserver_routes_test/user.json"), server.routes[0].response.result_file.as_deref());
+        assert_eq!(Some("/tmp/httpserver_routes_test/routes.d/bodies/order.json"), server.routes[3].response.result_file.as_deref());
+
+        // The inline route wins over the one of routes_file
+        let stubs = Stubs::new(&server.routes);
+        let (id, _, _) = find_route(&stubs.routes, &Method::GET, "/users/1", |_| true).unwrap();
+        assert_eq!(0, id);
+    }
+
+    #[tokio::test]
+    async fn httpserver_openapi() {
+        let dir = Path::new("/tmp/httpserver_openapi_test");
+        fs::create_dir_all(dir).unwrap();
+        fs::write(dir.join("spec.yaml"), r#"
+openapi: 3.0.3
+paths:
+  /users/{id}:
+    get:
+      parameters:
+      - {name: id, in: path, required: true, schema: {type: integer}}
+      responses:
+        '200':
+          description: a user
+          content:
+            application/json:
+              example: {id: 1, name: alice}
+  /users:
+    post:
+      requestBody:
+        required: true
+        content:
+          application/json:
+            schema:
+              type: object
+              required: [name]
+              properties:
+                name: {type: string}
+      responses:
+        '201':
+          description: created
+          content:
+            application/json:
+              schema:
+                type: object
+                properties:
+                  id: {type: integer}
+"#).unwrap();
+
+        let mut server: HttpServer = serde_yaml::from_str(r#"
+host_addr: "127.0.0.1:3011"
+scenario_dir: /tmp/httpserver_openapi_test
+openapi: spec.yaml
+validate_requests: true
+routes:
+- path: /users/2
+  method: GET
+  result: '{"id": 2, "name": "bob"}'
+"#).unwrap();
+        server.load_routes().unwrap();
+        server.validate_params().unwrap();
+
+        let (tx, rx) = tokio::sync::broadcast::channel(16);
+        tokio::spawn(async move{
+            let _ = server.func(tx, rx).await;
+        });
+
+        sleep(Duration::from_millis(100)).await;
+
+        let client = reqwest::Client::new();
+
+        let response = client.get("http://localhost:3011/users/1").send().await.unwrap();
+        assert_eq!(json!({"id": 1, "name": "alice"}), response.json::<jsonValue>().await.unwrap());
+
+        // The explicit route overrides the operation
+        let response = client.get("http://localhost:3011/users/2").send().await.unwrap();
+        assert_eq!(json!({"id": 2, "name": "bob"}), response.json::<jsonValue>().await.unwrap());
+
+        let response = client.post("http://localhost:3011/users").json(&json!({"name": "carol"})).send().await.unwrap();
+        assert_eq!(201, response.status().as_u16());
+        assert_eq!(json!({"id": 0}), response.json::<jsonValue>().await.unwrap());
+
+        let response = client.get("http://localhost:3011/users/alice").send().await.unwrap();
+        assert_eq!(400, response.status().as_u16());
+
+        let response = client.post("http://localhost:3011/users").json(&json!({"name": 3})).send().await.unwrap();
+        assert_eq!(400, response.status().as_u16());
+        assert_eq!(json!(["$.name: expected string, got 3"]), response.json::<jsonValue>().await.unwrap()["violations"]);
+    }
+
+    #[test]
+    fn httpserver_closest_route() {
+        let server: HttpServer = serde_yaml::from_str(r#"
+host_addr: "127.0.0.1:3012"
+routes:
+- path: /users
+  method: GET
+- path: /events
+  method: POST
+  body: '{"type": "created", "payload": {"id": 1}}'
+- path: /events
+  method: POST
+  headers:
+    x-tenant:
+      equals: acme
+"#).unwrap();
+        server.validate_params().unwrap();
+
+        let stubs = Stubs::new(&server.routes);
+        let headers = HeaderMap::new();
+        let query = BTreeMap::new();
+
+        let closest = closest_route(&stubs.routes, &Method::POST, "/events", &query, &headers, br#"{"type": "created", "payload": {"id": 2}}"#);
+        assert_eq!(Some("closest route POST /events (id 1): path matched, body differed at `$.payload.id`".to_string()), closest);
+
+        let closest = closest_route(&stubs.routes, &Method::DELETE, "/users", &query, &headers, b"");
+        assert_eq!(Some("closest route GET /users (id 0): path matched, method differed (expected GET)".to_string()), closest);
+
+        assert!(closest_route(&[], &Method::GET, "/users", &query, &headers, b"").is_none());
+    }
+
+    #[tokio::test]
+    async fn httpserver_admin() {
+        let server: HttpServer = serde_yaml::from_str(r#"
+host_addr: "127.0.0.1:3008"
+admin_addr: "127.0.0.1:3009"
+routes:
+- path: /users/:id
+  method: "GET"
+  responses:
+  - status: 404
+  - status: 200
+"#).unwrap();
+        server.validate_params().unwrap();
+
+        let (tx, rx) = tokio::sync::broadcast::channel(16);
+        tokio::spawn(async move{
+            let _ = server.func(tx, rx).await;
+        });
+
+        sleep(Duration::from_millis(100)).await;
+
+        let client = reqwest::Client::new();
+
+        // The admin API is not served by the stubs
+        assert_eq!(404, client.get("http://localhost:3008/__admin/routes").send().await.unwrap().status().as_u16());
+        assert_eq!(404, client.get("http://localhost:3008/users/1").send().await.unwrap().status().as_u16());
+
+        let response = client.post("http://localhost:3009/__admin/routes")
+            .json(&json!({"path": "/orders", "method": "POST", "status": 201, "result": "{\"created\": true}"}))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(201, response.status().as_u16());
+        let id = response.json::<jsonValue>().await.unwrap()["id"].as_u64().unwrap();
+        assert_eq!(1, id);
+
+        let response = client.post("http://localhost:3008/orders").send().await.unwrap();
+        assert_eq!(201, response.status().as_u16());
+
+        let routes = client.get("http://localhost:3009/__admin/routes").send().await.unwrap().json::<jsonValue>().await.unwrap();
+        assert_eq!(json!([0, 1]), json!(routes.as_array().unwrap().iter().map(|r| r["id"].clone()).collect::<Vec<jsonValue>>()));
+
+        let invalid = client.post("http://localhost:3009/__admin/routes")
+            .json(&json!({"path": "/orders", "method": "FETCH"}))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(400, invalid.status().as_u16());
+
+        let requests = client.get("http://localhost:3009/__admin/requests").send().await.unwrap().json::<Vec<JournalEntry>>().await.unwrap();
+        assert_eq!(3, requests.len());
+        assert_eq!(Some(1), requests[2].matched.as_ref().unwrap().id);
+
+        assert_eq!(200, client.delete(format!("http://localhost:3009/__admin/routes/{}", id)).send().await.unwrap().status().as_u16());
+        assert_eq!(404, client.delete(format!("http://localhost:3009/__admin/routes/{}", id)).send().await.unwrap().status().as_u16());
+        assert_eq!(404, client.post("http://localhost:3008/orders").send().await.unwrap().status().as_u16());
+
+        // The sequence restarts after a reset of the counters
+        assert_eq!(200, client.get("http://localhost:3008/users/1").send().await.unwrap().status().as_u16());
+        client.post("http://localhost:3009/__admin/counters/reset").send().await.unwrap();
+        assert_eq!(404, client.get("http://localhost:3008/users/1").send().await.unwrap().status().as_u16());
+
+        assert_eq!(204, client.post("http://localhost:3009/__admin/reset").send().await.unwrap().status().as_u16());
+        let requests = client.get("http://localhost:3009/__admin/requests").send().await.unwrap().json::<jsonValue>().await.unwrap();
+        assert_eq!(json!([]), requests);
+    }
 }
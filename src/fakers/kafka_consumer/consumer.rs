@@ -1,7 +1,7 @@
 use serde::{Deserialize, Serialize};
 //use std::collections::HashMap;
 use serde_yaml::{Mapping, Value as yamlValue};
-use serde_json::{json, Value as jsonValue};
+use serde_json::{json, Map, Value as jsonValue};
 
 use std::{fs::File, io::Seek};
 use std::io::Write;
@@ -20,7 +20,10 @@ use async_trait::async_trait;
 
 use log::*;
 
+use chrono::Utc;
+
 use crate::faker::{Faker, FakerMod};
+use crate::utils::codec::{Decoder, SchemaConfig, ValueFormat};
 
 use rdkafka::{
     config::{ClientConfig, RDKafkaLogLevel},
@@ -41,7 +44,11 @@ struct Config {
     topics: Vec<String>,
     options: Mapping,
     #[serde(default = "default_loglevel")]
-    log_level: String
+    log_level: String,
+    #[serde(default)]
+    value_format: ValueFormat,
+    #[serde(default)]
+    schema: SchemaConfig,
 }
 
 fn default_group_id() -> String {
@@ -64,6 +71,8 @@ impl Default for Config {
             topics: vec![],
             options: Mapping::new(),
             log_level: "info".to_string(),
+            value_format: ValueFormat::default(),
+            schema: SchemaConfig::default(),
         }
     }
 }
@@ -74,6 +83,9 @@ struct KafkaConsumer {
     brokers: Vec<String>,
     config: Config,
     output_file: Option<String>,
+    /// Record the key, partition, offset, timestamps and headers of each message in the output file
+    #[serde(default)]
+    metadata: bool,
 }
 
 #[async_trait]
@@ -89,6 +101,8 @@ impl FakerMod for KafkaConsumer {
             return Err(anyhow!("topics cannot be empty"));
         }
 
+        self.config.schema.validate(&self.config.value_format)?;
+
         Ok(())
     }
 
@@ -121,10 +135,12 @@ impl FakerMod for KafkaConsumer {
 
         let config = self.config.clone();
         let output_file = self.output_file.clone();
+        let metadata = self.metadata;
 
         Box::pin(async move {
             let topics: Vec<&str> = config.topics.iter().map(|t| t.as_ref()).collect();
             let consumer: StreamConsumer  = client_config.create()?;
+            let mut decoder = Decoder::new(&config.value_format, &config.schema)?;
 
             consumer.subscribe(&topics)?;
 
@@ -195,54 +211,38 @@ impl FakerMod for KafkaConsumer {
                 match consumer.recv().await {
                     Err(e) => { warn!("{e}"); },
                     Ok(m) => {
+                        let received_at = Utc::now().timestamp_millis();
+
                         debug!("key: 'd{:?}', topic: {}, partition: {}, offset: {}, timestamp: {:?}",
                             m.key(), m.topic(), m.partition(), m.offset(), m.timestamp());
 
+                        let mut header_values = Map::new();
                         if let Some(headers) = m.headers() {
                             for i in 0..headers.count() {
                                 let header = headers.get(i).unwrap();
                                 debug!("Header {:#?}: {:?}", header.0, header.1);
+
+                                header_values.insert(header.0.to_string(), jsonValue::String(String::from_utf8_lossy(header.1).to_string()));
                             }
                         }
 
-                        match m.payload_view::<str>() {
-                            Some(Ok(payload)) => {
-                                debug!("Payload received from kafka topic {}: {}", m.topic(), payload);
-
-                                if let Some(ref mut f) = f_output {
-                                    let payload_value: jsonValue = match serde_json::from_str(payload) {
-                                        Ok(v) => v,
-                                        Err(_e) => jsonValue::String(payload.to_string()),
-                                    };
+                        let payload_value = match m.payload() {
+                            Some(bytes) => match decoder.decode(bytes).await {
+                                Ok(v) => v,
+                                Err(e) => {
+                                    // One undecodable record must not stop the consumer
+                                    error!("consumer: skipping the message {}/{} at offset {}: {}", m.topic(), m.partition(), m.offset(), e);
 
-                                    let value = json_map!(
-                                        "topic" => jsonValue::String(m.topic().to_string()),
-                                        "message" => payload_value
-                                    );
+                                    if let Err(e) = consumer.commit_message(&m, CommitMode::Async) {
+                                        error!("consumer: {}", e);
+                                        return Err(anyhow!(e));
+                                    }
 
-                                    result.push(jsonValue::Object(value));
-
-                                    let text = serde_json::to_string_pretty(&jsonValue::Array(result.clone())).unwrap();
-
-                                    //if let Err(e) = serde_json::to_writer(f, &value) {
-                                        //error!("consumer: {}", e);
-                                        //return Err(anyhow!(e));
-                                    //}
-                                    //serde_json::to_writer(f, b"\n").unwrap();
-                                    f.rewind().unwrap();
-                                    f.write_all(text.as_bytes()).unwrap();
-                                    f.write_all(b"\n").unwrap();
-                                }
-
-                                if let Err(e) = consumer.commit_message(&m, CommitMode::Async) {
-                                    error!("consumer: {}", e);
-                                    return Err(anyhow!(e));
-                                }
-                            },
-                            Some(Err(e)) => {
-                                error!("consumer: {}", e);
-                                return Err(anyhow!(e));
+                                    continue;
+                                },
                             },
+                            // Tombstones are part of the recorded traffic
+                            None if metadata => jsonValue::Null,
                             None => {
                                 let e = anyhow!("No content received from the topic");
                                 error!("{e}");
@@ -250,6 +250,39 @@ impl FakerMod for KafkaConsumer {
                                 return Err(e);
                             },
                         };
+
+                        debug!("Payload received from kafka topic {}: {}", m.topic(), payload_value);
+
+                        if let Some(ref mut f) = f_output {
+                            let mut value = json_map!(
+                                "topic" => jsonValue::String(m.topic().to_string()),
+                                "message" => payload_value
+                            );
+
+                            if metadata {
+                                let key = m.key().map(|k| String::from_utf8_lossy(k).to_string());
+
+                                value.insert("key".to_string(), json!(key));
+                                value.insert("partition".to_string(), json!(m.partition()));
+                                value.insert("offset".to_string(), json!(m.offset()));
+                                value.insert("timestamp".to_string(), json!(m.timestamp().to_millis()));
+                                value.insert("headers".to_string(), jsonValue::Object(header_values));
+                                value.insert("received_at".to_string(), json!(received_at));
+                            }
+
+                            result.push(jsonValue::Object(value));
+
+                            let text = serde_json::to_string_pretty(&jsonValue::Array(result.clone())).unwrap();
+
+                            f.rewind().unwrap();
+                            f.write_all(text.as_bytes()).unwrap();
+                            f.write_all(b"\n").unwrap();
+                        }
+
+                        if let Err(e) = consumer.commit_message(&m, CommitMode::Async) {
+                            error!("consumer: {}", e);
+                            return Err(anyhow!(e));
+                        }
                     }
                 };
             }
@@ -0,0 +1,139 @@
+use serde::{Deserialize, Serialize};
+
+use anyhow::{anyhow, Result};
+
+use rand::{Rng, SeedableRng, rngs::StdRng, seq::SliceRandom};
+
+/// Perturbations applied to the produced messages. Percentages are between 0 and 100.
+#[derive(Default, Debug ,Serialize, Deserialize, Clone, PartialEq)]
+pub struct Chaos {
+    /// Seed of the random generator. A random seed is used (and logged) if not specified.
+    #[serde(default)]
+    pub seed: Option<u64>,
+    #[serde(default)]
+    pub duplicate_pct: f64,
+    #[serde(default)]
+    pub drop_pct: f64,
+    /// Messages are shuffled within consecutive windows of this size (0 or 1 = no reordering)
+    #[serde(default)]
+    pub reorder_window: usize,
+    #[serde(default)]
+    pub delay_pct: f64,
+    #[serde(default)]
+    pub delay_min_ms: u64,
+    #[serde(default)]
+    pub delay_max_ms: u64,
+}
+
+/// A message to send, after perturbation.
+#[derive(Default, Debug, Clone, PartialEq)]
+pub struct PlannedMessage {
+    /// Index of the message in the producer's messages
+    pub index: usize,
+    pub delay_ms: u64,
+    /// Perturbations applied: duplicate, reordered, delayed
+    pub perturbations: Vec<String>,
+}
+
+impl Chaos {
+    pub fn validate(&self) -> Result<()> {
+        for (name, pct) in [("duplicate_pct", self.duplicate_pct), ("drop_pct", self.drop_pct), ("delay_pct", self.delay_pct)] {
+            if !(0.0..=100.0).contains(&pct) {
+                return Err(anyhow!("chaos.{} must be between 0 and 100", name));
+            }
+        }
+
+        if self.delay_min_ms > self.delay_max_ms {
+            return Err(anyhow!("chaos.delay_min_ms cannot be greater than chaos.delay_max_ms"));
+        }
+
+        Ok(())
+    }
+
+    /// Return the messages to send in order, and the indexes of the dropped ones.
+    pub fn plan(&self, seed: u64, count: usize) -> (Vec<PlannedMessage>, Vec<usize>) {
+        let mut rng = StdRng::seed_from_u64(seed);
+
+        let mut planned = Vec::new();
+        let mut dropped = Vec::new();
+
+        for index in 0..count {
+            if rng.gen_bool(self.drop_pct / 100.0) {
+                dropped.push(index);
+                continue;
+            }
+
+            planned.push(PlannedMessage { index, ..Default::default() });
+
+            if rng.gen_bool(self.duplicate_pct / 100.0) {
+                planned.push(PlannedMessage {
+                    index,
+                    perturbations: vec!["duplicate".to_string()],
+                    ..Default::default()
+                });
+            }
+        }
+
+        if self.reorder_window > 1 {
+            for window in planned.chunks_mut(self.reorder_window) {
+                let original: Vec<PlannedMessage> = window.to_vec();
+                window.shuffle(&mut rng);
+
+                for (m, o) in window.iter_mut().zip(original.iter()) {
+                    if *m != *o {
+                        m.perturbations.push("reordered".to_string());
+                    }
+                }
+            }
+        }
+
+        for m in planned.iter_mut() {
+            if rng.gen_bool(self.delay_pct / 100.0) {
+                m.delay_ms = rng.gen_range(self.delay_min_ms..=self.delay_max_ms);
+                m.perturbations.push("delayed".to_string());
+            }
+        }
+
+        (planned, dropped)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn chaos_plan() {
+        let chaos = Chaos {
+            duplicate_pct: 30.0,
+            drop_pct: 20.0,
+            reorder_window: 3,
+            delay_pct: 50.0,
+            delay_min_ms: 10,
+            delay_max_ms: 100,
+            ..Default::default()
+        };
+
+        let (planned, dropped) = chaos.plan(42, 20);
+
+        // Reproducible with the same seed
+        assert_eq!((planned.clone(), dropped.clone()), chaos.plan(42, 20));
+
+        let duplicates = planned.iter().filter(|m| m.perturbations.contains(&"duplicate".to_string())).count();
+        assert_eq!(20 - dropped.len() + duplicates, planned.len());
+
+        for m in planned.iter() {
+            assert!(!dropped.contains(&m.index));
+            assert!(m.delay_ms == 0 || (10..=100).contains(&m.delay_ms));
+        }
+    }
+
+    #[test]
+    fn chaos_no_perturbation() {
+        let (planned, dropped) = Chaos::default().plan(1, 5);
+
+        assert!(dropped.is_empty());
+        assert_eq!(vec![0, 1, 2, 3, 4], planned.iter().map(|m| m.index).collect::<Vec<usize>>());
+        assert!(planned.iter().all(|m| m.perturbations.is_empty()));
+    }
+}
@@ -1 +1,3 @@
 mod producer;
+mod chaos;
+mod replay;
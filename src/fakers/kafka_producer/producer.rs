@@ -1,35 +1,72 @@
 use serde::{Deserialize, Serialize};
 use serde_yaml::{Value, Mapping};
+use serde_json::{json, Map, Value as jsonValue};
 
 use tokio::sync::broadcast::{Sender, Receiver};
 //use async_channel::{Sender, Receiver};
 //use tokio::runtime::Runtime;
 use async_trait::async_trait;
 
+use std::collections::{BTreeMap, HashMap};
+use std::fs::File;
+use std::io::Write;
 use std::time::Duration;
 
+use tokio::time::{sleep, sleep_until, Instant};
+
+use chrono::Utc;
+use uuid::Uuid;
+
 use anyhow::{anyhow, Result, Error};
 
 use log::*;
 
 use rdkafka::{
     config::{ClientConfig, RDKafkaLogLevel},
-    producer::future_producer::{FutureProducer, FutureRecord},
+    message::OwnedHeaders,
+    producer::{
+        future_producer::{FutureProducer, FutureRecord},
+        Producer,
+    },
 };
 
 use futures::Future;
 use std::pin::Pin;
 
 use crate::faker::{Faker, FakerMod};
+use super::chaos::{Chaos, PlannedMessage};
+use super::replay::Replay;
+use crate::utils::codec::{message_to_json, Encoder, SchemaConfig, ValueFormat};
 
 // Our plugin implementation
 #[derive(Default, Debug ,Serialize, Deserialize, Clone)]
 struct KafkaProducer {
     brokers: Vec<String>,
     options: Mapping,
+    #[serde(default)]
     messages: Vec<Message>,
     #[serde(default = "default_loglevel")]
     log_level: String,
+    #[serde(default)]
+    value_format: ValueFormat,
+    #[serde(default)]
+    schema: SchemaConfig,
+    /// Header injected in every message with a unique id, to correlate produced & consumed messages
+    #[serde(default)]
+    correlation_header: Option<String>,
+    /// File recording the produced messages with their timestamps
+    #[serde(default)]
+    output_file: Option<String>,
+    #[serde(default)]
+    chaos: Option<Chaos>,
+    /// Send the messages recorded by kafka-consumer instead of `messages`
+    #[serde(default)]
+    replay: Option<Replay>,
+    /// Produce in transactions. Without `transactions`, all the messages are sent in a single committed one.
+    #[serde(default)]
+    transactional_id: Option<String>,
+    #[serde(default)]
+    transactions: Vec<Transaction>,
 }
 
 fn default_loglevel() -> String {
@@ -39,8 +76,46 @@ fn default_loglevel() -> String {
 #[derive(Default, Debug ,Serialize, Deserialize, Clone)]
 struct Message {
     topic: String,
+    #[serde(default)]
     key: String,
-    message: String,
+    #[serde(default)]
+    headers: BTreeMap<String, String>,
+    #[serde(default)]
+    partition: Option<i32>,
+    /// Original timestamp of a replayed message
+    #[serde(skip)]
+    timestamp: Option<i64>,
+    /// Index of the transaction the message belongs to
+    #[serde(skip)]
+    transaction: Option<usize>,
+    message: Value,
+}
+
+#[derive(Default, Debug ,Serialize, Deserialize, Clone)]
+struct Transaction {
+    /// Abort the transaction instead of committing it
+    #[serde(default)]
+    abort: bool,
+    messages: Vec<Message>,
+}
+
+impl KafkaProducer {
+    /// Whether each transaction must be aborted, empty if the producer is not transactional.
+    ///
+    /// Without `transactions`, all the messages are sent in a single committed transaction.
+    fn transaction_aborts(&self) -> Vec<bool> {
+        match (&self.transactional_id, self.transactions.is_empty()) {
+            (None, _) => vec![],
+            (Some(_), true) => vec![false],
+            (Some(_), false) => self.transactions.iter().map(|t| t.abort).collect(),
+        }
+    }
+}
+
+/// Put the messages of each transaction back together after the chaos mode reordered them,
+/// so a transaction is never split. The order within a transaction is kept.
+fn group_by_transaction(planned: &mut [PlannedMessage], messages: &[Message]) {
+    planned.sort_by_key(|p| messages[p.index].transaction.unwrap_or(0));
 }
 
 #[async_trait]
@@ -52,16 +127,43 @@ impl FakerMod for KafkaProducer {
             return Err(anyhow!("brokers cannot be empty"));
         }
 
-        if self.messages.is_empty() {
-            return Err(anyhow!("messages cannot be empty"));
+        let sources = [!self.messages.is_empty(), self.replay.is_some(), !self.transactions.is_empty()];
+        match sources.iter().filter(|s| **s).count() {
+            0 => return Err(anyhow!("messages cannot be empty")),
+            1 => {},
+            _ => return Err(anyhow!("Only one of messages, replay or transactions can be set")),
         }
 
-        for m in self.messages.iter() {
-            if m.topic.is_empty() || m.message.is_empty() {
+        if let Some(r) = &self.replay {
+            r.validate()?;
+        }
+
+        if !self.transactions.is_empty() && self.transactional_id.is_none() {
+            return Err(anyhow!("transactions require a transactional_id"));
+        }
+
+        if self.transactional_id.as_ref().is_some_and(|t| t.is_empty()) {
+            return Err(anyhow!("transactional_id cannot be empty"));
+        }
+
+        for (i, t) in self.transactions.iter().enumerate() {
+            if t.messages.is_empty() {
+                return Err(anyhow!("Messages of the transaction #{} cannot be empty", i));
+            }
+        }
+
+        for m in self.messages.iter().chain(self.transactions.iter().flat_map(|t| t.messages.iter())) {
+            if m.topic.is_empty() || m.message.is_null() || m.message.as_str() == Some("") {
                 return Err(anyhow!("Topic or message must not be empty!"));
             }
         }
 
+        self.schema.validate(&self.value_format)?;
+
+        if let Some(c) = &self.chaos {
+            c.validate()?;
+        }
+
         Ok(())
     }
 
@@ -72,6 +174,10 @@ impl FakerMod for KafkaProducer {
 
         client_config.set("bootstrap.servers", self.brokers.join(","));
 
+        if let Some(id) = &self.transactional_id {
+            client_config.set("transactional.id", id);
+        }
+
         match self.log_level.as_str() {
             "debug" => client_config.set_log_level(RDKafkaLogLevel::Debug),
             "info" => client_config.set_log_level(RDKafkaLogLevel::Info),
@@ -90,37 +196,195 @@ impl FakerMod for KafkaProducer {
             }
         }
 
-        let messages = self.messages.clone();
+        let messages = match &self.replay {
+            Some(r) => match load_replay(r) {
+                Ok(m) => m,
+                Err(e) => return Box::pin(async move { Err(e) }),
+            },
+            None if !self.transactions.is_empty() => self.transactions.iter()
+                .enumerate()
+                .flat_map(|(i, t)| t.messages.iter().cloned().map(move |m| Message { transaction: Some(i), ..m }))
+                .collect(),
+            None => self.messages.clone(),
+        };
+
+        let aborts = self.transaction_aborts();
+        let replay = self.replay.clone();
+        let value_format = self.value_format.clone();
+        let schema = self.schema.clone();
+        let correlation_header = self.correlation_header.clone();
+        let output_file = self.output_file.clone();
+
+        let chaos = self.chaos.clone().unwrap_or_default();
+        let seed = chaos.seed.unwrap_or_else(rand::random::<u64>);
+        if self.chaos.is_some() {
+            info!("Kafka producer chaos mode enabled with the seed {}", seed);
+        }
+        let (mut planned, dropped) = chaos.plan(seed, messages.len());
+        if !aborts.is_empty() {
+            group_by_transaction(&mut planned, &messages);
+        }
 
         Box::pin(async move {
 
             let producer: FutureProducer  = client_config.create()?;
 
-            for msg in messages.clone().iter() {
+            // Transaction in progress
+            let mut current: Option<usize> = None;
+            if !aborts.is_empty() {
+                producer.init_transactions(Duration::from_secs(30))?;
+            }
+            let mut encoders: HashMap<String, Encoder> = HashMap::new();
+            let mut result = Vec::<jsonValue>::new();
+
+            // Original relative timing of the replayed messages
+            let replay_start = Instant::now();
+            let first_timestamp = messages.iter().find_map(|m| m.timestamp);
+
+            for i in dropped.iter() {
+                let msg = &messages[*i];
+                info!("Chaos: dropping message #{} to the topic {:?}", i, msg.topic);
+
+                result.push(json!({
+                    "index": i,
+                    "topic": msg.topic,
+                    "key": msg.key,
+                    "message": message_to_json(&msg.message).unwrap_or(jsonValue::Null),
+                    "dropped": true,
+                    "chaos": ["dropped"],
+                }));
+            }
+
+            for p in planned.iter() {
+                let msg = &messages[p.index];
+
+                if p.delay_ms > 0 {
+                    info!("Chaos: delaying message #{} by {}ms", p.index, p.delay_ms);
+                    sleep(Duration::from_millis(p.delay_ms)).await;
+                }
+
+                if let Some(r) = &replay {
+                    let offset = r.offset_ms(first_timestamp, msg.timestamp);
+                    sleep_until(replay_start + Duration::from_millis(offset)).await;
+                }
+
                 info!("Sending messages to the topic {:?}", msg.topic);
 
-                let mut fr = FutureRecord::to(msg.topic.as_str())
-                        .payload(msg.message.as_bytes());
+                // Schemas from the registry are looked up by subject, so one encoder per topic
+                if !encoders.contains_key(&msg.topic) {
+                    let encoder = Encoder::new(&value_format, &schema, &msg.topic).await?;
+                    encoders.insert(msg.topic.clone(), encoder);
+                }
+
+                if !aborts.is_empty() {
+                    let t = msg.transaction.unwrap_or(0);
+
+                    if current != Some(t) {
+                        if let Some(c) = current {
+                            end_transaction(&producer, c, aborts[c])?;
+                        }
+
+                        info!("Beginning the transaction #{}", t);
+                        producer.begin_transaction()?;
+                        current = Some(t);
+                    }
+                }
+
+                // A null message is sent as a tombstone
+                let payload = match msg.message {
+                    Value::Null => None,
+                    _ => Some(encoders[&msg.topic].encode(&msg.message)?),
+                };
+
+                let mut fr: FutureRecord<[u8], [u8]> = FutureRecord::to(msg.topic.as_str());
+
+                if let Some(p) = &payload {
+                    fr = fr.payload(p.as_slice());
+                }
 
                 if !msg.key.is_empty() {
                     fr =  fr.key(msg.key.as_bytes());
                 }
 
+                if let Some(p) = msg.partition {
+                    fr = fr.partition(p);
+                }
+
+                let mut headers = Map::new();
+                let mut owned_headers = OwnedHeaders::new();
+                for (k, v) in msg.headers.iter() {
+                    owned_headers = owned_headers.add(k.as_str(), v);
+                    headers.insert(k.clone(), jsonValue::String(v.clone()));
+                }
+
+                if let Some(h) = &correlation_header {
+                    let id = Uuid::new_v4().to_string();
+
+                    owned_headers = owned_headers.add(h.as_str(), &id);
+                    headers.insert(h.clone(), jsonValue::String(id));
+                }
+
+                if !headers.is_empty() {
+                    fr = fr.headers(owned_headers);
+                }
+
+                let sent_at = Utc::now().timestamp_millis();
+
                 let produce_future = producer.send(
                     fr,
-                    //.headers(OwnedHeaders::new().add("header_key", "header_value")),
                     Duration::from_secs(0),
                 );
 
-                match produce_future.await {
+                let (partition, offset) = match produce_future.await {
                     Ok(delivery) => {
                         debug!("Kafka producer sent delivery status: {:?}", delivery);
+                        delivery
                     }
                     Err((e, _)) => {
                         error!("Kafka producer sent error: {:?}", e);
+
+                        // What the transaction already sent must not be committed by the next producer
+                        if let Some(c) = current {
+                            if let Err(abort_error) = end_transaction(&producer, c, true) {
+                                error!("Aborting the transaction #{}: {}", c, abort_error);
+                            }
+                            mark_aborted(&mut result, c);
+                        }
+
+                        if let Some(o) = &output_file {
+                            write_output(o, result)?;
+                        }
+
                         return Err(anyhow!(e));
                     }
                 };
+
+                let mut entry = json!({
+                    "index": p.index,
+                    "topic": msg.topic,
+                    "key": msg.key,
+                    "partition": partition,
+                    "offset": offset,
+                    "headers": headers,
+                    "message": message_to_json(&msg.message).unwrap_or(jsonValue::Null),
+                    "sent_at": sent_at,
+                    "chaos": p.perturbations,
+                });
+
+                if let Some(t) = current {
+                    entry["transaction"] = json!(t);
+                    entry["aborted"] = json!(aborts[t]);
+                }
+
+                result.push(entry);
+            }
+
+            if let Some(c) = current {
+                end_transaction(&producer, c, aborts[c])?;
+            }
+
+            if let Some(o) = &output_file {
+                write_output(o, result)?;
             }
 
             tx.send(true)?;
@@ -130,6 +394,54 @@ impl FakerMod for KafkaProducer {
     }
 }
 
+fn end_transaction(producer: &FutureProducer, index: usize, abort: bool) -> Result<()> {
+    if abort {
+        info!("Aborting the transaction #{}", index);
+        producer.abort_transaction(Duration::from_secs(30))?;
+    } else {
+        info!("Committing the transaction #{}", index);
+        producer.commit_transaction(Duration::from_secs(30))?;
+    }
+
+    Ok(())
+}
+
+/// Mark the messages sent in the transaction as aborted.
+fn mark_aborted(result: &mut [jsonValue], transaction: usize) {
+    for entry in result.iter_mut().filter(|e| e["transaction"] == json!(transaction)) {
+        entry["aborted"] = json!(true);
+    }
+}
+
+fn write_output(file: &str, result: Vec<jsonValue>) -> Result<()> {
+    let mut f = File::create(file)?;
+    let text = serde_json::to_string_pretty(&jsonValue::Array(result))?;
+
+    f.write_all(text.as_bytes())?;
+    f.write_all(b"\n")?;
+
+    Ok(())
+}
+
+/// Turn the records of kafka-consumer into messages to send.
+fn load_replay(replay: &Replay) -> Result<Vec<Message>> {
+    let records = replay.load()?;
+
+    info!("Replaying {} message(s) from {}", records.len(), replay.file);
+
+    records.into_iter()
+        .map(|r| Ok(Message {
+            topic: r.topic,
+            key: r.key.unwrap_or_default(),
+            headers: r.headers,
+            partition: r.partition,
+            timestamp: r.timestamp,
+            transaction: None,
+            message: serde_yaml::to_value(r.message)?,
+        }))
+        .collect()
+}
+
 fn func(params: Mapping, tx: Sender<bool>, rx: Receiver<bool>) -> Pin<Box<dyn Future<Output = Result<(), Error>> + Send>> {
     Box::pin(async move {
         let v_params = Value::Mapping(params);
@@ -145,3 +457,70 @@ fn func(params: Mapping, tx: Sender<bool>, rx: Receiver<bool>) -> Pin<Box<dyn Fu
 }
 
 inventory::submit!(Faker {name: "kafka-producer", func: func });
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn producer(yaml: &str) -> KafkaProducer {
+        serde_yaml::from_str(&format!("brokers: [localhost:9092]\noptions: {{}}\n{}", yaml)).unwrap()
+    }
+
+    #[test]
+    fn producer_transaction_aborts() {
+        let transactions = r#"
+transactions:
+  - messages: [{topic: orders, message: a}]
+  - abort: true
+    messages: [{topic: orders, message: b}]
+"#;
+
+        assert!(producer("messages: [{topic: orders, message: a}]").transaction_aborts().is_empty());
+        assert_eq!(vec![false], producer("transactional_id: tx\nmessages: [{topic: orders, message: a}]").transaction_aborts());
+        assert_eq!(vec![false, true], producer(&format!("transactional_id: tx\n{}", transactions)).transaction_aborts());
+    }
+
+    #[test]
+    fn producer_mark_aborted() {
+        let mut result = vec![
+            json!({"index": 0, "transaction": 0, "aborted": false}),
+            json!({"index": 1, "transaction": 1, "aborted": false}),
+            json!({"index": 2, "transaction": 1, "aborted": false}),
+            json!({"index": 3, "dropped": true}),
+        ];
+
+        mark_aborted(&mut result, 1);
+
+        let aborted: Vec<bool> = result.iter().map(|e| e["aborted"] == json!(true)).collect();
+        assert_eq!(vec![false, true, true, false], aborted);
+    }
+
+    #[test]
+    fn producer_group_by_transaction() {
+        let messages: Vec<Message> = [0, 0, 1, 1, 2]
+            .iter()
+            .map(|t| Message { transaction: Some(*t), ..Default::default() })
+            .collect();
+
+        let chaos = Chaos { reorder_window: 5, duplicate_pct: 50.0, ..Default::default() };
+        let (mut planned, _) = chaos.plan(7, messages.len());
+
+        group_by_transaction(&mut planned, &messages);
+
+        let transactions: Vec<usize> = planned.iter().map(|p| messages[p.index].transaction.unwrap()).collect();
+        let mut sorted = transactions.clone();
+        sorted.sort();
+        assert_eq!(sorted, transactions);
+
+        // The chaos order is kept within a transaction
+        let plan = |t: usize| -> Vec<usize> {
+            chaos.plan(7, messages.len()).0.iter()
+                .map(|p| p.index)
+                .filter(|i| messages[*i].transaction == Some(t))
+                .collect()
+        };
+        for t in 0..3 {
+            assert_eq!(plan(t), planned.iter().map(|p| p.index).filter(|i| messages[*i].transaction == Some(t)).collect::<Vec<usize>>());
+        }
+    }
+}
@@ -0,0 +1,129 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value as jsonValue;
+
+use std::collections::BTreeMap;
+use std::fs::File;
+
+use anyhow::{anyhow, Result};
+
+/// Replay of the traffic recorded by kafka-consumer (with metadata: true)
+#[derive(Debug ,Serialize, Deserialize, Clone)]
+pub struct Replay {
+    /// Output file of kafka-consumer
+    pub file: String,
+    /// Speed factor applied to the original timing (2.0 = twice as fast, 0 = no delay)
+    #[serde(default = "default_speed")]
+    pub speed: f64,
+    /// Send the messages to this topic instead of the recorded one
+    #[serde(default)]
+    pub topic: Option<String>,
+    /// Send each message to its recorded partition
+    #[serde(default = "default_preserve_partitions")]
+    pub preserve_partitions: bool,
+}
+
+fn default_speed() -> f64 {
+    1.0
+}
+
+fn default_preserve_partitions() -> bool {
+    true
+}
+
+/// A message as recorded by kafka-consumer.
+#[derive(Default, Debug ,Serialize, Deserialize, Clone, PartialEq)]
+pub struct RecordedMessage {
+    pub topic: String,
+    #[serde(default)]
+    pub key: Option<String>,
+    #[serde(default)]
+    pub partition: Option<i32>,
+    #[serde(default)]
+    pub timestamp: Option<i64>,
+    #[serde(default)]
+    pub headers: BTreeMap<String, String>,
+    /// Null for tombstones
+    #[serde(default)]
+    pub message: jsonValue,
+}
+
+impl Replay {
+    pub fn validate(&self) -> Result<()> {
+        if self.file.is_empty() {
+            return Err(anyhow!("replay.file cannot be empty"));
+        }
+
+        if self.speed < 0.0 {
+            return Err(anyhow!("replay.speed cannot be negative"));
+        }
+
+        Ok(())
+    }
+
+    pub fn load(&self) -> Result<Vec<RecordedMessage>> {
+        let f = File::open(&self.file)?;
+        let records: Vec<RecordedMessage> = serde_json::from_reader(f)
+            .map_err(|e| anyhow!("Invalid recording {}: {}", self.file, e))?;
+
+        Ok(self.prepare(records))
+    }
+
+    /// Sort the records by timestamp and apply the topic & partition settings.
+    fn prepare(&self, mut records: Vec<RecordedMessage>) -> Vec<RecordedMessage> {
+        // Stable sort: messages without timestamp keep their position relative to each other
+        records.sort_by_key(|r| r.timestamp.unwrap_or(i64::MIN));
+
+        for r in records.iter_mut() {
+            if let Some(t) = &self.topic {
+                r.topic = t.clone();
+            }
+
+            if !self.preserve_partitions {
+                r.partition = None;
+            }
+        }
+
+        records
+    }
+
+    /// Delay in ms from the start of the replay at which the record must be sent.
+    pub fn offset_ms(&self, first: Option<i64>, timestamp: Option<i64>) -> u64 {
+        match (first, timestamp) {
+            (Some(f), Some(t)) if self.speed > 0.0 && t > f => ((t - f) as f64 / self.speed) as u64,
+            _ => 0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn replay_prepare() {
+        let replay = Replay {
+            file: "recording.json".to_string(),
+            speed: 2.0,
+            topic: Some("replayed".to_string()),
+            preserve_partitions: false,
+        };
+
+        let records: Vec<RecordedMessage> = serde_json::from_value(json!([
+            {"topic": "t", "key": "k2", "partition": 1, "offset": 3, "timestamp": 1200, "headers": {"h": "v"}, "message": {"v": 2}},
+            {"topic": "t", "key": null, "partition": 0, "offset": 7, "timestamp": 1000, "headers": {}, "message": null},
+        ])).unwrap();
+
+        let records = replay.prepare(records);
+
+        assert_eq!(None, records[0].key);
+        assert_eq!(jsonValue::Null, records[0].message);
+        assert_eq!(Some("k2".to_string()), records[1].key);
+        assert_eq!("v", records[1].headers["h"]);
+        assert!(records.iter().all(|r| r.topic == "replayed" && r.partition.is_none()));
+
+        assert_eq!(0, replay.offset_ms(records[0].timestamp, records[0].timestamp));
+        assert_eq!(100, replay.offset_ms(records[0].timestamp, records[1].timestamp));
+        assert_eq!(0, replay.offset_ms(None, records[1].timestamp));
+    }
+}
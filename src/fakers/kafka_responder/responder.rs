@@ -0,0 +1,472 @@
+use serde::{Deserialize, Serialize};
+use serde_yaml::{Mapping, Value as yamlValue};
+use serde_json::{json, Map, Value as jsonValue};
+
+use std::collections::{BTreeMap, HashMap};
+use std::fs::File;
+use std::io::{Seek, Write};
+use std::time::Duration;
+
+use anyhow::{anyhow, Result, Error};
+
+use futures::Future;
+use std::pin::Pin;
+
+use tokio::sync::broadcast::{Sender, Receiver};
+use tokio::time::sleep;
+use async_trait::async_trait;
+
+use log::*;
+
+use rdkafka::{
+    config::{ClientConfig, RDKafkaLogLevel},
+    consumer::{
+        stream_consumer::StreamConsumer,
+        Consumer,
+        CommitMode,
+    },
+    message::{Headers, Message, OwnedHeaders},
+    producer::future_producer::{FutureProducer, FutureRecord},
+};
+
+use crate::faker::{Faker, FakerMod};
+use crate::utils::codec::{Decoder, Encoder, SchemaConfig, ValueFormat};
+use crate::utils::matcher::MessageMatcher;
+use crate::utils::template;
+
+// Our plugin implementation
+#[derive(Default, Debug ,Serialize, Deserialize, Clone)]
+struct KafkaResponder {
+    brokers: Vec<String>,
+    #[serde(default = "default_group_id")]
+    group_id: String,
+    request_topic: String,
+    reply_topic: String,
+    #[serde(default)]
+    error_topic: Option<String>,
+    #[serde(default)]
+    correlation: Correlation,
+    #[serde(default)]
+    options: Mapping,
+    #[serde(default)]
+    value_format: ValueFormat,
+    #[serde(default)]
+    schema: SchemaConfig,
+    rules: Vec<Rule>,
+    output_file: Option<String>,
+    #[serde(default = "default_loglevel")]
+    log_level: String,
+}
+
+fn default_group_id() -> String {
+    "kafka-responder".to_string()
+}
+
+fn default_loglevel() -> String {
+    "info".to_string()
+}
+
+/// How a reply is correlated to its request.
+#[derive(Debug ,Serialize, Deserialize, Clone)]
+struct Correlation {
+    /// Header holding the correlation id, copied from the request to the reply.
+    #[serde(default = "default_correlation_header")]
+    header: Option<String>,
+    /// Use the request key as the reply key.
+    #[serde(default)]
+    key: bool,
+}
+
+fn default_correlation_header() -> Option<String> {
+    Some("correlation-id".to_string())
+}
+
+impl Default for Correlation {
+    fn default() -> Self {
+        Correlation {
+            header: default_correlation_header(),
+            key: false,
+        }
+    }
+}
+
+#[derive(Default, Debug ,Serialize, Deserialize, Clone)]
+struct Rule {
+    #[serde(default)]
+    name: String,
+    #[serde(flatten)]
+    matcher: MessageMatcher,
+    reply: Reply,
+}
+
+#[derive(Default, Debug ,Serialize, Deserialize, Clone)]
+struct Reply {
+    /// Topic overriding the responder's reply_topic.
+    #[serde(default)]
+    topic: Option<String>,
+    #[serde(default)]
+    key: Option<String>,
+    #[serde(default)]
+    headers: BTreeMap<String, String>,
+    #[serde(default)]
+    message: String,
+    #[serde(default)]
+    delay_ms: u64,
+    /// Reply with an error: the reply goes to error_topic (if specified) with the header error=true.
+    #[serde(default)]
+    error: bool,
+}
+
+/// Reply rendered for a request, ready to be sent.
+#[derive(Debug ,Serialize, Clone, PartialEq)]
+struct OutgoingReply {
+    topic: String,
+    key: Option<String>,
+    headers: BTreeMap<String, String>,
+    message: String,
+}
+
+impl KafkaResponder {
+    /// First rule matching the request, None if no rule matches.
+    fn find_rule(&self, key: Option<&str>, headers: &Map<String, jsonValue>, body: &jsonValue) -> Option<&Rule> {
+        self.rules.iter().find(|r| r.matcher.matches(key, headers, body))
+    }
+
+    /// Render the reply of the rule from the request: topic, key, headers & message.
+    fn render_reply(&self, rule: &Rule, request: &jsonValue, correlation_id: Option<&str>) -> Result<OutgoingReply> {
+        let context = json!({
+            "request": request,
+            "correlation_id": correlation_id,
+        });
+
+        let reply = &rule.reply;
+
+        let topic = match (&reply.topic, reply.error, &self.error_topic) {
+            (Some(t), _, _) => t.clone(),
+            (None, true, Some(t)) => t.clone(),
+            _ => self.reply_topic.clone(),
+        };
+
+        let key = match &reply.key {
+            Some(k) => Some(template::render(k, &context)?),
+            None if self.correlation.key => request["key"].as_str().map(|k| k.to_string()),
+            None => None,
+        };
+
+        let mut headers = BTreeMap::new();
+        if let (Some(h), Some(id)) = (&self.correlation.header, correlation_id) {
+            headers.insert(h.clone(), id.to_string());
+        }
+        if reply.error {
+            headers.insert("error".to_string(), "true".to_string());
+        }
+        for (name, value) in reply.headers.iter() {
+            headers.insert(name.clone(), template::render(value, &context)?);
+        }
+
+        let message = template::render(&reply.message, &context)?;
+
+        Ok(OutgoingReply { topic, key, headers, message })
+    }
+}
+
+async fn send_reply(producer: &FutureProducer, reply: &OutgoingReply, payload: &[u8]) -> Result<()> {
+    let mut owned_headers = OwnedHeaders::new();
+    for (name, value) in reply.headers.iter() {
+        owned_headers = owned_headers.add(name, value);
+    }
+
+    let mut fr = FutureRecord::to(reply.topic.as_str())
+        .payload(payload)
+        .headers(owned_headers);
+
+    if let Some(k) = &reply.key {
+        fr = fr.key(k.as_bytes());
+    }
+
+    if let Err((e, _)) = producer.send(fr, Duration::from_secs(0)).await {
+        return Err(anyhow!(e));
+    }
+
+    Ok(())
+}
+
+#[async_trait]
+impl FakerMod for KafkaResponder {
+    type Future = Pin<Box<dyn Future<Output = Result<(), Error>> + Send>>;
+
+    fn validate_params(&self) -> Result<()> {
+        if self.brokers.is_empty() {
+            return Err(anyhow!("brokers cannot be empty"));
+        }
+
+        if self.request_topic.is_empty() || self.reply_topic.is_empty() {
+            return Err(anyhow!("request_topic or reply_topic cannot be empty"));
+        }
+
+        if self.rules.is_empty() {
+            return Err(anyhow!("rules cannot be empty"));
+        }
+
+        self.schema.validate(&self.value_format)?;
+
+        Ok(())
+    }
+
+    fn func(&self, _tx: Sender<bool>, _rx: Receiver<bool>) -> Self::Future {
+        let _ =  env_logger::try_init();
+
+        let mut client_config = ClientConfig::new();
+
+        client_config.set("bootstrap.servers", self.brokers.join(","));
+
+        match self.log_level.as_str() {
+            "debug" => client_config.set_log_level(RDKafkaLogLevel::Debug),
+            "info" => client_config.set_log_level(RDKafkaLogLevel::Info),
+            "notice" => client_config.set_log_level(RDKafkaLogLevel::Notice),
+            "warning" => client_config.set_log_level(RDKafkaLogLevel::Warning),
+            "error" => client_config.set_log_level(RDKafkaLogLevel::Error),
+            "critical" => client_config.set_log_level(RDKafkaLogLevel::Critical),
+            "alert" => client_config.set_log_level(RDKafkaLogLevel::Alert),
+            "emerg" => client_config.set_log_level(RDKafkaLogLevel::Emerg),
+            _ => client_config.set_log_level(RDKafkaLogLevel::Info),
+        };
+
+        for (k, v) in self.options.iter() {
+            if let Some(s) = v.as_str() {
+                client_config.set(k.as_str().unwrap(), s);
+            }
+        }
+
+        let mut consumer_config = client_config.clone();
+        consumer_config.set("group.id", self.group_id.clone())
+            .set("auto.offset.reset", "earliest");
+
+        let responder = self.clone();
+
+        Box::pin(async move {
+            let consumer: StreamConsumer = consumer_config.create()?;
+            let producer: FutureProducer = client_config.create()?;
+
+            let mut decoder = Decoder::new(&responder.value_format, &responder.schema)?;
+            let mut encoders: HashMap<String, Encoder> = HashMap::new();
+
+            let mut f_output = match &responder.output_file {
+                Some(o) => Some(File::create(o)?),
+                None => None,
+            };
+            let mut result = Vec::<jsonValue>::new();
+
+            consumer.subscribe(&[responder.request_topic.as_str()])?;
+
+            info!("Waiting for requests on the topic {}...", responder.request_topic);
+
+            loop {
+                let m = match consumer.recv().await {
+                    Ok(m) => m,
+                    Err(e) => { warn!("{e}"); continue; },
+                };
+
+                let key = m.key().map(|k| String::from_utf8_lossy(k).to_string());
+
+                let mut headers = Map::new();
+                if let Some(hs) = m.headers() {
+                    for i in 0..hs.count() {
+                        if let Some((name, value)) = hs.get(i) {
+                            headers.insert(name.to_string(), jsonValue::String(String::from_utf8_lossy(value).to_string()));
+                        }
+                    }
+                }
+
+                let body = match m.payload() {
+                    Some(bytes) => match decoder.decode(bytes).await {
+                        Ok(v) => v,
+                        Err(e) => {
+                            // A malformed request must not stop the responder
+                            error!("Skipping the request {}/{} at offset {}: {}", m.topic(), m.partition(), m.offset(), e);
+                            consumer.commit_message(&m, CommitMode::Async)?;
+                            continue;
+                        },
+                    },
+                    None => jsonValue::Null,
+                };
+
+                debug!("Request received: key: {:?}, headers: {:?}, body: {}", key, headers, body);
+
+                let correlation_id = match &responder.correlation.header {
+                    Some(h) => headers.get(h).and_then(|v| v.as_str()).map(|v| v.to_string()),
+                    None => None,
+                };
+
+                let request = json!({
+                    "topic": m.topic(),
+                    "partition": m.partition(),
+                    "offset": m.offset(),
+                    "key": key.clone(),
+                    "headers": headers.clone(),
+                    "body": body.clone(),
+                });
+
+                let rule = match responder.find_rule(key.as_deref(), &headers, &body) {
+                    Some(r) => r,
+                    None => {
+                        warn!("No rule matched the request: {}", request);
+                        consumer.commit_message(&m, CommitMode::Async)?;
+                        continue;
+                    },
+                };
+
+                let reply = match responder.render_reply(rule, &request, correlation_id.as_deref()) {
+                    Ok(r) => r,
+                    Err(e) => {
+                        // Neither must a template failing on one request
+                        error!("Skipping the request {}/{} at offset {}: {}", m.topic(), m.partition(), m.offset(), e);
+                        consumer.commit_message(&m, CommitMode::Async)?;
+                        continue;
+                    },
+                };
+
+                if !encoders.contains_key(&reply.topic) {
+                    let encoder = Encoder::new(&responder.value_format, &responder.schema, &reply.topic).await?;
+                    encoders.insert(reply.topic.clone(), encoder);
+                }
+                let payload = encoders[&reply.topic].encode(&yamlValue::String(reply.message.clone()))?;
+
+                info!("Rule {} matched, replying to the topic {}", rule.name, reply.topic);
+
+                if rule.reply.delay_ms > 0 {
+                    // Delayed replies are sent from their own task, so they don't hold up the next requests
+                    let producer = producer.clone();
+                    let delay_ms = rule.reply.delay_ms;
+                    let reply = reply.clone();
+
+                    tokio::spawn(async move {
+                        sleep(Duration::from_millis(delay_ms)).await;
+
+                        if let Err(e) = send_reply(&producer, &reply, &payload).await {
+                            error!("Kafka responder sent error: {:?}", e);
+                        }
+                    });
+                } else if let Err(e) = send_reply(&producer, &reply, &payload).await {
+                    error!("Kafka responder sent error: {:?}", e);
+                    return Err(e);
+                }
+
+                if let Some(ref mut f) = f_output {
+                    result.push(json!({
+                        "rule": rule.name,
+                        "request": request,
+                        "reply": reply,
+                    }));
+
+                    let text = serde_json::to_string_pretty(&jsonValue::Array(result.clone())).unwrap();
+
+                    f.rewind()?;
+                    f.write_all(text.as_bytes())?;
+                    f.write_all(b"\n")?;
+                }
+
+                consumer.commit_message(&m, CommitMode::Async)?;
+            }
+        })
+    }
+}
+
+fn func(params: Mapping, tx: Sender<bool>, rx: Receiver<bool>) -> Pin<Box<dyn Future<Output = Result<(), Error>> + Send>> {
+    Box::pin(async move {
+        let v_params = yamlValue::Mapping(params);
+
+        let responder: KafkaResponder = serde_yaml::from_value(v_params)?;
+
+        responder.validate_params()?;
+        responder.func(tx, rx).await?;
+
+        Ok(())
+
+    })
+}
+
+inventory::submit!(Faker {name: "kafka-responder", func: func });
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn responder() -> KafkaResponder {
+        serde_yaml::from_str(r#"
+brokers: [localhost:9092]
+request_topic: orders.requests
+reply_topic: orders.replies
+error_topic: orders.errors
+correlation:
+  key: true
+rules:
+  - name: unknown
+    body:
+      contains: {sku: unknown}
+    reply:
+      error: true
+      message: '{"error": "unknown sku {{request.body.sku}}"}'
+  - name: priority
+    headers:
+      priority: {equals: high}
+    reply:
+      topic: orders.priority
+      key: 'prio-{{request.key}}'
+      headers:
+        handled-by: '{{correlation_id}}'
+      message: '{"id": "{{request.body.id}}", "status": "accepted"}'
+  - name: default
+    reply:
+      message: '{"id": "{{request.body.id}}", "status": "queued"}'
+"#).unwrap()
+    }
+
+    #[test]
+    fn responder_find_rule() {
+        let responder = responder();
+
+        let mut headers = Map::new();
+        let body = json!({"id": "o1", "sku": "a"});
+
+        assert_eq!("default", responder.find_rule(None, &headers, &body).unwrap().name);
+        assert_eq!("unknown", responder.find_rule(None, &headers, &json!({"id": "o1", "sku": "unknown"})).unwrap().name);
+
+        headers.insert("priority".to_string(), json!("high"));
+        assert_eq!("priority", responder.find_rule(Some("k1"), &headers, &body).unwrap().name);
+
+        let mut strict = responder.clone();
+        strict.rules.retain(|r| r.name != "default");
+        assert!(strict.find_rule(None, &Map::new(), &body).is_none());
+    }
+
+    #[test]
+    fn responder_render_reply() {
+        let responder = responder();
+        let request = json!({
+            "topic": "orders.requests",
+            "key": "k1",
+            "headers": {"correlation-id": "c1"},
+            "body": {"id": "o1", "sku": "unknown"},
+        });
+
+        let reply = responder.render_reply(&responder.rules[2], &request, Some("c1")).unwrap();
+        assert_eq!(OutgoingReply {
+            topic: "orders.replies".to_string(),
+            key: Some("k1".to_string()),
+            headers: BTreeMap::from([("correlation-id".to_string(), "c1".to_string())]),
+            message: r#"{"id": "o1", "status": "queued"}"#.to_string(),
+        }, reply);
+
+        let reply = responder.render_reply(&responder.rules[1], &request, Some("c1")).unwrap();
+        assert_eq!("orders.priority", reply.topic);
+        assert_eq!(Some("prio-k1".to_string()), reply.key);
+        assert_eq!(Some(&"c1".to_string()), reply.headers.get("handled-by"));
+
+        let reply = responder.render_reply(&responder.rules[0], &request, None).unwrap();
+        assert_eq!("orders.errors", reply.topic);
+        assert_eq!(Some(&"true".to_string()), reply.headers.get("error"));
+        assert!(!reply.headers.contains_key("correlation-id"));
+        assert_eq!(r#"{"error": "unknown sku unknown"}"#, reply.message);
+    }
+}
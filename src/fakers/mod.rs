@@ -1,3 +1,5 @@
 pub mod kafka_producer;
 pub mod kafka_consumer;
+pub mod kafka_responder;
 pub mod http_server;
+pub mod schema_registry;
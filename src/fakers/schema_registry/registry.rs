@@ -0,0 +1,514 @@
+use std::net::{SocketAddr, SocketAddrV4};
+use std::sync::{Arc, Mutex};
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::Path as FsPath;
+
+use serde::{Serialize, Deserialize};
+use serde_yaml::{Mapping, Value as yamlValue};
+use serde_json::{json, Value as jsonValue};
+
+use anyhow::{anyhow, Result, Error};
+
+use tokio::sync::broadcast::{Sender, Receiver};
+use async_trait::async_trait;
+
+use log::*;
+
+use axum::Router;
+use axum::routing::*;
+use axum::body::Bytes;
+use axum::extract::{Json, Extension, Path};
+use axum::http::StatusCode;
+
+use futures::{Future, future};
+use std::pin::Pin;
+
+use crate::faker::{Faker, FakerMod};
+
+// Our plugin implementation
+#[derive(Default, Debug ,Serialize, Deserialize, Clone, PartialEq)]
+struct SchemaRegistry {
+    host_addr: String,
+    #[serde(default)]
+    subjects: Vec<Subject>,
+    output_file: Option<String>
+}
+
+#[derive(Default, Debug ,Serialize, Deserialize, Clone, PartialEq)]
+struct Subject {
+    subject: String,
+    file: String,
+    /// AVRO, PROTOBUF or JSON. Guessed from the file extension if not specified.
+    schema_type: Option<String>,
+}
+
+#[derive(Default, Debug ,Serialize, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+struct RegisterRequest {
+    schema: String,
+    #[serde(default)]
+    schema_type: Option<String>,
+    #[serde(default)]
+    references: Vec<jsonValue>,
+}
+
+#[derive(Default, Debug, Clone)]
+struct StoredSchema {
+    id: u32,
+    schema: String,
+    schema_type: String,
+}
+
+#[derive(Default, Debug)]
+struct Registry {
+    schemas: Vec<StoredSchema>,
+    // Subject => list of schema ids, the version being the position + 1
+    subjects: BTreeMap<String, Vec<u32>>,
+    // Registrations done by the system under test
+    registrations: Vec<jsonValue>,
+    output_file: String,
+}
+
+impl Registry {
+    fn find_schema(&self, id: u32) -> Option<&StoredSchema> {
+        self.schemas.iter().find(|s| s.id == id)
+    }
+
+    fn find_same_schema(&self, schema: &str, schema_type: &str) -> Option<&StoredSchema> {
+        self.schemas.iter().find(|s| s.schema_type == schema_type && same_schema(&s.schema, schema))
+    }
+
+    /// Register a schema under a subject and return its id and version.
+    fn register(&mut self, subject: &str, schema: &str, schema_type: &str) -> (u32, usize) {
+        let existing = self.find_same_schema(schema, schema_type).map(|s| s.id);
+
+        let id = match existing {
+            Some(id) => id,
+            None => {
+                let id = self.schemas.len() as u32 + 1;
+                self.schemas.push(StoredSchema {
+                    id,
+                    schema: schema.to_string(),
+                    schema_type: schema_type.to_string(),
+                });
+
+                id
+            },
+        };
+
+        let versions = self.subjects.entry(subject.to_string()).or_default();
+        match versions.iter().position(|v| *v == id) {
+            Some(p) => (id, p + 1),
+            None => {
+                versions.push(id);
+                (id, versions.len())
+            },
+        }
+    }
+
+    /// Return the schema id & version number of a subject's version ("latest" or a number).
+    fn get_version(&self, subject: &str, version: &str) -> Result<(u32, usize), (StatusCode, Json<jsonValue>)> {
+        let versions = match self.subjects.get(subject) {
+            Some(v) if !v.is_empty() => v,
+            _ => return Err(registry_error(StatusCode::NOT_FOUND, 40401, "Subject not found.")),
+        };
+
+        let n = match version {
+            "latest" | "-1" => versions.len(),
+            v => match v.parse::<usize>() {
+                Ok(n) if (1..=versions.len()).contains(&n) => n,
+                Ok(_) => return Err(registry_error(StatusCode::NOT_FOUND, 40402, "Version not found.")),
+                Err(_) => return Err(registry_error(StatusCode::UNPROCESSABLE_ENTITY, 42202, "Invalid version.")),
+            },
+        };
+
+        Ok((versions[n - 1], n))
+    }
+
+    fn write_output_file(&self) -> Result<()> {
+        if !self.output_file.is_empty() {
+            let mut f = File::create(&self.output_file)?;
+            let text = serde_json::to_string_pretty(&self.registrations)?;
+
+            f.write_all(text.as_bytes())?;
+            f.write_all(b"\n")?;
+        }
+
+        Ok(())
+    }
+}
+
+type SharedRegistry = Arc<Mutex<Registry>>;
+
+/// Compare 2 schemas, ignoring the formatting when both are JSON documents.
+fn same_schema(s1: &str, s2: &str) -> bool {
+    match (serde_json::from_str::<jsonValue>(s1), serde_json::from_str::<jsonValue>(s2)) {
+        (Ok(v1), Ok(v2)) => v1 == v2,
+        _ => s1.trim() == s2.trim(),
+    }
+}
+
+fn guess_schema_type(file: &str) -> String {
+    match FsPath::new(file).extension().and_then(|e| e.to_str()) {
+        Some("proto") => "PROTOBUF".to_string(),
+        Some("json") => "JSON".to_string(),
+        _ => "AVRO".to_string(),
+    }
+}
+
+fn registry_error(status: StatusCode, code: u32, message: &str) -> (StatusCode, Json<jsonValue>) {
+    (status, Json(json!({"error_code": code, "message": message})))
+}
+
+fn schema_json(subject: &str, version: usize, schema: &StoredSchema) -> jsonValue {
+    let mut v = json!({
+        "subject": subject,
+        "version": version,
+        "id": schema.id,
+        "schema": schema.schema,
+    });
+
+    // AVRO is the default type and is omitted by the registry
+    if schema.schema_type != "AVRO" {
+        v["schemaType"] = jsonValue::String(schema.schema_type.clone());
+    }
+
+    v
+}
+
+#[async_trait]
+impl FakerMod for SchemaRegistry {
+    type Future = Pin<Box<dyn Future<Output = Result<(), Error>> + Send>>;
+
+    fn validate_params(&self) -> Result<()> {
+        if self.host_addr.is_empty() {
+            return Err(anyhow!("host_addr cannot be empty"));
+        }
+
+        for s in self.subjects.iter() {
+            if s.subject.is_empty() || s.file.is_empty() {
+                return Err(anyhow!("subject or file must not be empty!"));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn func(&self, _tx: Sender<bool>, _rx: Receiver<bool>) -> Self::Future {
+        let _ =  env_logger::try_init();
+
+        let mut registry = Registry {
+            output_file: self.output_file.clone().unwrap_or_default(),
+            ..Default::default()
+        };
+
+        for s in self.subjects.iter() {
+            let schema = match fs::read_to_string(&s.file) {
+                Ok(c) => c,
+                Err(e) => return Box::pin(future::err(anyhow!("Reading schema file {}: {}", s.file, e))),
+            };
+
+            let schema_type = s.schema_type.clone().unwrap_or_else(|| guess_schema_type(&s.file));
+            let (id, version) = registry.register(&s.subject, &schema, &schema_type);
+
+            info!("Pre-registered subject: {}, version: {}, id: {}", s.subject, version, id);
+        }
+
+        let shared_registry: SharedRegistry = Arc::new(Mutex::new(registry));
+
+        let app = Router::new()
+            .route("/subjects", get(list_subjects))
+            .route("/subjects/:subject", post(lookup_schema).delete(delete_subject))
+            .route("/subjects/:subject/versions", get(list_versions).post(register_schema))
+            .route("/subjects/:subject/versions/:version", get(get_version))
+            .route("/subjects/:subject/versions/:version/schema", get(get_version_schema))
+            .route("/schemas/ids/:id", get(get_schema_by_id))
+            .route("/schemas/ids/:id/versions", get(get_schema_versions))
+            .route("/schemas/types", get(list_schema_types))
+            .route("/config", get(get_config))
+            .route("/config/:subject", get(get_config))
+            .layer(Extension(shared_registry));
+
+        let addr = match self.host_addr.parse::<SocketAddrV4>() {
+            Ok(a) => a,
+            Err(e) => return Box::pin(future::err(anyhow!(e))),
+        };
+
+        info!("Listening on {}", addr);
+        Box::pin(async move {
+            if let Err(e) = axum::Server::bind(&SocketAddr::V4(addr))
+                .serve(app.into_make_service())
+                .await {
+                return Err(anyhow!(e));
+            }
+
+            Ok(())
+        })
+    }
+}
+
+async fn list_subjects(
+    Extension(registry): Extension<SharedRegistry>,
+) -> (StatusCode, Json<jsonValue>) {
+    let registry = registry.lock().unwrap();
+    let subjects: Vec<&String> = registry.subjects.keys().collect();
+
+    (StatusCode::OK, Json(json!(subjects)))
+}
+
+async fn list_versions(
+    Path(subject): Path<String>,
+    Extension(registry): Extension<SharedRegistry>,
+) -> (StatusCode, Json<jsonValue>) {
+    let registry = registry.lock().unwrap();
+
+    match registry.subjects.get(&subject) {
+        Some(versions) => {
+            let numbers: Vec<usize> = (1..=versions.len()).collect();
+            (StatusCode::OK, Json(json!(numbers)))
+        },
+        None => registry_error(StatusCode::NOT_FOUND, 40401, "Subject not found."),
+    }
+}
+
+async fn get_version(
+    Path((subject, version)): Path<(String, String)>,
+    Extension(registry): Extension<SharedRegistry>,
+) -> (StatusCode, Json<jsonValue>) {
+    let registry = registry.lock().unwrap();
+
+    let (id, n) = match registry.get_version(&subject, &version) {
+        Ok(v) => v,
+        Err(e) => return e,
+    };
+
+    match registry.find_schema(id) {
+        Some(s) => (StatusCode::OK, Json(schema_json(&subject, n, s))),
+        None => registry_error(StatusCode::NOT_FOUND, 40403, "Schema not found."),
+    }
+}
+
+async fn get_version_schema(
+    Path((subject, version)): Path<(String, String)>,
+    Extension(registry): Extension<SharedRegistry>,
+) -> (StatusCode, Json<jsonValue>) {
+    let registry = registry.lock().unwrap();
+
+    let (id, _) = match registry.get_version(&subject, &version) {
+        Ok(v) => v,
+        Err(e) => return e,
+    };
+
+    match registry.find_schema(id) {
+        Some(s) => match serde_json::from_str::<jsonValue>(&s.schema) {
+            Ok(v) => (StatusCode::OK, Json(v)),
+            Err(_) => (StatusCode::OK, Json(jsonValue::String(s.schema.clone()))),
+        },
+        None => registry_error(StatusCode::NOT_FOUND, 40403, "Schema not found."),
+    }
+}
+
+async fn register_schema(
+    Path(subject): Path<String>,
+    body: Bytes,
+    Extension(registry): Extension<SharedRegistry>,
+) -> (StatusCode, Json<jsonValue>) {
+    let req: RegisterRequest = match serde_json::from_slice(&body) {
+        Ok(r) => r,
+        Err(e) => return registry_error(StatusCode::UNPROCESSABLE_ENTITY, 42201, &format!("Invalid schema: {}", e)),
+    };
+
+    let schema_type = req.schema_type.clone().unwrap_or_else(|| "AVRO".to_string());
+
+    let mut registry = registry.lock().unwrap();
+    let (id, version) = registry.register(&subject, &req.schema, &schema_type);
+
+    info!("Schema registered by a client: subject: {}, version: {}, id: {}", subject, version, id);
+
+    registry.registrations.push(json!({
+        "subject": subject,
+        "version": version,
+        "id": id,
+        "schemaType": schema_type,
+        "schema": req.schema,
+        "references": req.references,
+    }));
+    // The schema is registered anyway
+    if let Err(e) = registry.write_output_file() {
+        error!("schema-registry: writing the output file {}: {}", registry.output_file, e);
+    }
+
+    (StatusCode::OK, Json(json!({"id": id})))
+}
+
+async fn lookup_schema(
+    Path(subject): Path<String>,
+    body: Bytes,
+    Extension(registry): Extension<SharedRegistry>,
+) -> (StatusCode, Json<jsonValue>) {
+    let req: RegisterRequest = match serde_json::from_slice(&body) {
+        Ok(r) => r,
+        Err(e) => return registry_error(StatusCode::UNPROCESSABLE_ENTITY, 42201, &format!("Invalid schema: {}", e)),
+    };
+
+    let schema_type = req.schema_type.unwrap_or_else(|| "AVRO".to_string());
+    let registry = registry.lock().unwrap();
+
+    let versions = match registry.subjects.get(&subject) {
+        Some(v) => v,
+        None => return registry_error(StatusCode::NOT_FOUND, 40401, "Subject not found."),
+    };
+
+    let found = registry.find_same_schema(&req.schema, &schema_type)
+        .and_then(|s| versions.iter().position(|v| *v == s.id).map(|p| (s, p + 1)));
+
+    match found {
+        Some((s, n)) => (StatusCode::OK, Json(schema_json(&subject, n, s))),
+        None => registry_error(StatusCode::NOT_FOUND, 40403, "Schema not found."),
+    }
+}
+
+async fn delete_subject(
+    Path(subject): Path<String>,
+    Extension(registry): Extension<SharedRegistry>,
+) -> (StatusCode, Json<jsonValue>) {
+    let mut registry = registry.lock().unwrap();
+
+    match registry.subjects.remove(&subject) {
+        Some(versions) => {
+            let numbers: Vec<usize> = (1..=versions.len()).collect();
+            (StatusCode::OK, Json(json!(numbers)))
+        },
+        None => registry_error(StatusCode::NOT_FOUND, 40401, "Subject not found."),
+    }
+}
+
+async fn get_schema_by_id(
+    Path(id): Path<u32>,
+    Extension(registry): Extension<SharedRegistry>,
+) -> (StatusCode, Json<jsonValue>) {
+    let registry = registry.lock().unwrap();
+
+    match registry.find_schema(id) {
+        Some(s) => {
+            let mut v = json!({"schema": s.schema});
+            if s.schema_type != "AVRO" {
+                v["schemaType"] = jsonValue::String(s.schema_type.clone());
+            }
+
+            (StatusCode::OK, Json(v))
+        },
+        None => registry_error(StatusCode::NOT_FOUND, 40403, "Schema not found."),
+    }
+}
+
+async fn get_schema_versions(
+    Path(id): Path<u32>,
+    Extension(registry): Extension<SharedRegistry>,
+) -> (StatusCode, Json<jsonValue>) {
+    let registry = registry.lock().unwrap();
+
+    if registry.find_schema(id).is_none() {
+        return registry_error(StatusCode::NOT_FOUND, 40403, "Schema not found.");
+    }
+
+    let mut result = Vec::new();
+    for (subject, versions) in registry.subjects.iter() {
+        if let Some(p) = versions.iter().position(|v| *v == id) {
+            result.push(json!({"subject": subject, "version": p + 1}));
+        }
+    }
+
+    (StatusCode::OK, Json(jsonValue::Array(result)))
+}
+
+async fn list_schema_types() -> (StatusCode, Json<jsonValue>) {
+    (StatusCode::OK, Json(json!(["AVRO", "PROTOBUF", "JSON"])))
+}
+
+async fn get_config() -> (StatusCode, Json<jsonValue>) {
+    (StatusCode::OK, Json(json!({"compatibilityLevel": "NONE"})))
+}
+
+fn func(params: Mapping, tx: Sender<bool>, rx: Receiver<bool>) -> Pin<Box<dyn Future<Output = Result<(), Error>> + Send>> {
+    Box::pin(async move {
+        let v_params = yamlValue::Mapping(params);
+
+        let registry: SchemaRegistry = serde_yaml::from_value(v_params)?;
+
+        registry.validate_params()?;
+        registry.func(tx, rx).await?;
+
+        Ok(())
+
+    })
+}
+
+inventory::submit!(Faker {name: "schema-registry", func: func });
+
+#[cfg(test)]
+mod tests {
+    use reqwest::header::CONTENT_TYPE;
+
+    use super::*;
+    use tokio::time::{sleep, Duration};
+
+    #[tokio::test]
+    async fn schemaregistry_func() {
+        let file = std::env::temp_dir().join("test-faker-registry.avsc");
+        fs::write(&file, r#"{"type": "record", "name": "Order", "fields": [{"name": "id", "type": "string"}]}"#).unwrap();
+
+        let params: Mapping = serde_yaml::from_str(&format!(r#"
+host_addr: "127.0.0.1:3081"
+subjects:
+- subject: orders-value
+  file: {}
+output_file: /tmp/registry.json
+"#, file.display())).unwrap();
+
+        let (tx, rx) = tokio::sync::broadcast::channel(16);
+        let registry: SchemaRegistry = serde_yaml::from_value(yamlValue::Mapping(params)).unwrap();
+        registry.validate_params().unwrap();
+
+        tokio::spawn(async move{
+            let _ = registry.func(tx, rx).await;
+        });
+
+        sleep(Duration::from_secs(1)).await;
+
+        let client = reqwest::Client::new();
+
+        let response = client.get("http://localhost:3081/subjects/orders-value/versions/latest")
+            .send()
+            .await
+            .unwrap();
+
+        assert_eq!(200, response.status().as_u16());
+        let v = response.json::<jsonValue>().await.unwrap();
+        assert_eq!(json!(1), v["id"]);
+        assert_eq!(json!(1), v["version"]);
+
+        // Registration by a client
+        let response = client.post("http://localhost:3081/subjects/users-value/versions")
+            .header(CONTENT_TYPE, "application/vnd.schemaregistry.v1+json")
+            .body(r#"{"schema": "{\"type\": \"string\"}"}"#)
+            .send()
+            .await
+            .unwrap();
+
+        assert_eq!(200, response.status().as_u16());
+        assert_eq!(json!({"id": 2}), response.json::<jsonValue>().await.unwrap());
+
+        let response = client.get("http://localhost:3081/schemas/ids/2")
+            .send()
+            .await
+            .unwrap();
+
+        assert_eq!(json!({"schema": "{\"type\": \"string\"}"}), response.json::<jsonValue>().await.unwrap());
+
+        let registrations: jsonValue = serde_json::from_str(&fs::read_to_string("/tmp/registry.json").unwrap()).unwrap();
+        assert_eq!(json!("users-value"), registrations[0]["subject"]);
+    }
+}
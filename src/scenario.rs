@@ -1,13 +1,14 @@
 use log::*;
 use tracing::info;
 use std::fs::File;
+use std::path::Path;
 
 use serde::{Deserialize, Serialize};
-use serde_yaml::Mapping;
+use serde_yaml::{Mapping, Value};
 //use serde_json::Map;
 //use serde_json::Value as jsonValue;
 
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 
 use tokio::sync::*;
 use tokio::signal;
@@ -18,6 +19,11 @@ use crate::verifier;
 use crate::utils::*;
 //use crate::message::Message;
 
+/// Fakers resolving their relative files (routes, OpenAPI specification...) against the
+/// directory of the scenario, passed as the `scenario_dir` parameter. The others resolve them
+/// against the working directory.
+const SCENARIO_DIR_FAKERS: [&str; 1] = ["http-server"];
+
 #[derive(Default, Debug ,Serialize, Deserialize, Clone, PartialEq)]
 pub struct Scenario {
     #[serde(default)]
@@ -36,6 +42,10 @@ pub struct Scenario {
 
     #[serde(default)]
     pub options: Option<Options>,
+
+    /// Directory of the scenario file, given as `scenario_dir` to the fakers of SCENARIO_DIR_FAKERS
+    #[serde(skip)]
+    pub dir: Option<String>,
 }
 
 #[derive(Default, Debug ,Serialize, Deserialize, Clone, PartialEq)]
@@ -78,12 +88,18 @@ pub struct Teardown {
 pub struct Options {
     pub faker_launch_tempo: Option<u64>,
     pub termination_tempo: Option<u64>,
+    /// Fail the run if a faker received a request none of its stubs matched
+    #[serde(default)]
+    pub fail_on_unmatched: Option<bool>,
 }
 
 impl Scenario {
     pub fn new_from_file(file: &str) -> Self {
         let f = File::open(file).unwrap();
-        serde_yaml::from_reader(f).unwrap()
+        let mut scenario: Scenario = serde_yaml::from_reader(f).unwrap();
+        scenario.dir = Path::new(file).parent().map(|d| d.to_string_lossy().to_string());
+
+        scenario
     }
 
     #[allow(dead_code)]
@@ -91,10 +107,25 @@ impl Scenario {
         serde_yaml::from_str(content).unwrap()
     }
 
+    /// Parameters of a faker, with the directory of the scenario for the fakers resolving files against it.
+    fn faker_params(&self, f: &Faker) -> Mapping {
+        let mut params = f.params.clone();
+
+        if let (Some(dir), true) = (&self.dir, SCENARIO_DIR_FAKERS.contains(&f.name.as_str())) {
+            let key = Value::String("scenario_dir".to_string());
+            if !params.contains_key(&key) {
+                params.insert(key, Value::String(dir.clone()));
+            }
+        }
+
+        params
+    }
+
     pub async fn run(&mut self) -> Result<()> {
         let (tx, mut rx) = broadcast::channel(16);
         let mut faker_launch_tempo = 1;
         let mut termination_tempo = 3;
+        let mut fail_on_unmatched = false;
 
         if let Some(opts) = &self.options {
             if let Some(t) = opts.faker_launch_tempo {
@@ -104,6 +135,10 @@ impl Scenario {
             if let Some(t) = opts.termination_tempo {
                 termination_tempo = t;
             }
+
+            if let Some(f) = opts.fail_on_unmatched {
+                fail_on_unmatched = f;
+            }
         }
 
         info!("Launching setups...");
@@ -114,11 +149,16 @@ impl Scenario {
             }
         }
 
+        // Forget the requests of a previous run
+        let servers = self.http_servers();
+        unmatched::take(&servers);
+
         info!("Launching fakers...");
         for f in self.fakers.iter() {
             if let Some(f1) = faker::get_faker(f.name.as_str()) {
                 info!("Starting faker name: {}, params: {:?}", f.name, f.params);
-                let params = f.params.clone();
+                let params = self.faker_params(f);
+
                 let tx_cloned = tx.clone();
                 let rx_cloned = tx_cloned.subscribe();
                 tokio::spawn(async move {
@@ -144,23 +184,99 @@ impl Scenario {
         };
 
         info!("Launching verifiers...");
+        let mut verified = Ok(());
         for v in self.verifiers.iter() {
             if let Some(v1) = verifier::get_verifier(v.name.as_str()) {
                 info!("Starting verifier name: {}, params: {:?}", v.name, v.params);
                 let params = v.params.clone();
-                (v1.func)(params)?;
+                if let Err(e) = (v1.func)(params) {
+                    verified = Err(e);
+                    break;
+                }
             }
         }
 
-        Ok(())
+        // Reported along with a failing verifier, which must not hide them
+        let requests = unmatched::take(&servers);
+        if fail_on_unmatched && !requests.is_empty() {
+            let report = unmatched_report(&requests);
+            error!("{}", report);
+
+            return match verified {
+                Ok(()) => Err(anyhow!(report)),
+                Err(e) => Err(anyhow!("{}\n{}", report, e)),
+            };
+        }
+
+        verified
+    }
+
+    /// Addresses of the http-server fakers, identifying their unmatched requests.
+    fn http_servers(&self) -> Vec<String> {
+        self.fakers.iter()
+            .filter(|f| f.name == "http-server")
+            .filter_map(|f| f.params.get(&Value::String("host_addr".to_string())).and_then(|v| v.as_str()))
+            .map(|a| a.to_string())
+            .collect()
     }
 }
 
+/// Unmatched requests, grouped by server.
+fn unmatched_report(requests: &[(String, Vec<String>)]) -> String {
+    let count: usize = requests.iter().map(|(_, r)| r.len()).sum();
+
+    let mut report = format!("{} request(s) matched no stub:", count);
+    for (server, descriptions) in requests.iter() {
+        for d in descriptions.iter() {
+            report.push_str(&format!("\nhttp-server {}: {}", server, d));
+        }
+    }
+
+    report
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use serde_yaml::Value;
 
+    #[test]
+    fn scenario_unmatched_report() {
+        let scenario = Scenario::new_from_str(r#"
+fakers:
+  - name: http-server
+    params: {host_addr: "127.0.0.1:3000"}
+  - name: kafka-producer
+    params: {brokers: [localhost:9092]}
+"#);
+        assert_eq!(vec!["127.0.0.1:3000".to_string()], scenario.http_servers());
+
+        let requests = vec![
+            ("127.0.0.1:3000".to_string(), vec!["GET /a: no route defined".to_string()]),
+            ("127.0.0.1:3001".to_string(), vec!["GET /b: no route defined".to_string(), "GET /c: no route defined".to_string()]),
+        ];
+        assert_eq!(
+            "3 request(s) matched no stub:\nhttp-server 127.0.0.1:3000: GET /a: no route defined\nhttp-server 127.0.0.1:3001: GET /b: no route defined\nhttp-server 127.0.0.1:3001: GET /c: no route defined",
+            unmatched_report(&requests)
+        );
+    }
+
+    #[test]
+    fn scenario_faker_params() {
+        let mut scenario = Scenario::new_from_str(r#"
+fakers:
+  - name: http-server
+    params: {host_addr: "127.0.0.1:3000"}
+  - name: schema-registry
+    params: {host_addr: "127.0.0.1:3081"}
+"#);
+        scenario.dir = Some("scenarios/orders".to_string());
+
+        let key = Value::String("scenario_dir".to_string());
+        assert_eq!(Some(&Value::String("scenarios/orders".to_string())), scenario.faker_params(&scenario.fakers[0]).get(&key));
+        assert!(!scenario.faker_params(&scenario.fakers[1]).contains_key(&key));
+    }
+
     #[test]
     fn scenario_new_from_str() {
         let content = r#"
@@ -197,6 +313,7 @@ fakers:
             verifiers: vec![],
             teardown: None,
             options: None,
+            dir: None,
         };
 
         assert_eq!(expected, scenario);
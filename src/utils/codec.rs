@@ -0,0 +1,589 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+use serde_yaml::Value as yamlValue;
+use serde_json::Value as jsonValue;
+
+use anyhow::{anyhow, Result};
+
+use log::*;
+
+use apache_avro::types::Value as avroValue;
+use protobuf::reflect::{FileDescriptor, MessageDescriptor};
+
+/// Confluent wire format: magic byte followed by a 4-byte schema id.
+const MAGIC_BYTE: u8 = 0;
+
+#[derive(Default, Debug ,Serialize, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ValueFormat {
+    #[default]
+    String,
+    Json,
+    Avro,
+    Protobuf,
+}
+
+#[derive(Default, Debug ,Serialize, Deserialize, Clone, PartialEq)]
+pub struct SchemaConfig {
+    /// Local `.avsc` or `.proto` file. Payloads are encoded without any framing.
+    #[serde(default)]
+    pub file: Option<String>,
+    /// Schema registry URL. Payloads use the Confluent wire format.
+    #[serde(default)]
+    pub registry_url: Option<String>,
+    /// Subject used to look up the schema in the registry (default: `<topic>-value`).
+    #[serde(default)]
+    pub subject: Option<String>,
+    /// Subject version used to look up the schema in the registry (default: `latest`).
+    #[serde(default)]
+    pub version: Option<String>,
+    /// Fully-qualified protobuf message name (default: first message of the file).
+    #[serde(default)]
+    pub message_type: Option<String>,
+}
+
+impl SchemaConfig {
+    pub fn validate(&self, format: &ValueFormat) -> Result<()> {
+        if *format != ValueFormat::Avro && *format != ValueFormat::Protobuf {
+            return Ok(());
+        }
+
+        if self.file.is_none() && self.registry_url.is_none() {
+            return Err(anyhow!("schema.file or schema.registry_url must be specified for {:?}", format));
+        }
+
+        if self.file.is_some() && self.registry_url.is_some() {
+            return Err(anyhow!("schema.file and schema.registry_url are mutually exclusive"));
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Schema {
+    Avro(apache_avro::Schema),
+    Protobuf(MessageDescriptor),
+}
+
+impl Schema {
+    fn parse(format: &ValueFormat, text: &str, message_type: Option<&str>) -> Result<Self> {
+        match format {
+            ValueFormat::Avro => Ok(Schema::Avro(apache_avro::Schema::parse_str(text)?)),
+            ValueFormat::Protobuf => {
+                // The pure Rust parser only works on files, so registry schemas are
+                // written to their own temporary directory, removed once parsed.
+                let dir = tempfile::tempdir()?;
+
+                let path = dir.path().join("schema.proto");
+                fs::write(&path, text)?;
+
+                Ok(Schema::Protobuf(load_proto_message(&path, message_type)?))
+            },
+            _ => Err(anyhow!("No schema for the format {:?}", format)),
+        }
+    }
+
+    fn load(format: &ValueFormat, file: &str, message_type: Option<&str>) -> Result<Self> {
+        match format {
+            ValueFormat::Protobuf => Ok(Schema::Protobuf(load_proto_message(Path::new(file), message_type)?)),
+            _ => Schema::parse(format, &fs::read_to_string(file)?, message_type),
+        }
+    }
+
+    fn encode(&self, value: &jsonValue) -> Result<Vec<u8>> {
+        match self {
+            Schema::Avro(s) => {
+                let v = avroValue::from(value.clone()).resolve(s)?;
+
+                Ok(apache_avro::to_avro_datum(s, v)?)
+            },
+            Schema::Protobuf(d) => {
+                let msg = protobuf_json_mapping::parse_dyn_from_str(d, &value.to_string())?;
+
+                Ok(msg.write_to_bytes_dyn()?)
+            },
+        }
+    }
+
+    fn decode(&self, bytes: &[u8]) -> Result<jsonValue> {
+        match self {
+            Schema::Avro(s) => {
+                let v = apache_avro::from_avro_datum(s, &mut &bytes[..], None)?;
+
+                Ok(jsonValue::try_from(v)?)
+            },
+            Schema::Protobuf(d) => {
+                let msg = d.parse_from_bytes(bytes)?;
+                let text = protobuf_json_mapping::print_to_string(msg.as_ref())?;
+
+                Ok(serde_json::from_str(&text)?)
+            },
+        }
+    }
+}
+
+/// Parse a `.proto` file (and its imports) and return the descriptor of the wanted message.
+fn load_proto_message(path: &Path, message_type: Option<&str>) -> Result<MessageDescriptor> {
+    let include = path.parent().unwrap_or_else(|| Path::new("."));
+
+    let parsed = protobuf_parse::Parser::new()
+        .pure()
+        .include(include)
+        .input(path)
+        .parse_and_typecheck()?;
+
+    let files = FileDescriptor::new_dynamic_fds(parsed.file_descriptors, &[])?;
+    let file_name = path.file_name().and_then(|n| n.to_str()).unwrap_or_default();
+
+    match message_type {
+        Some(t) => {
+            let full_name = format!(".{}", t.trim_start_matches('.'));
+
+            files.iter()
+                .find_map(|f| f.message_by_full_name(&full_name))
+                .ok_or_else(|| anyhow!("Protobuf message {} not found in {}", t, path.display()))
+        },
+        None => files.iter()
+            .find(|f| f.name() == file_name)
+            .and_then(|f| f.messages().next())
+            .ok_or_else(|| anyhow!("No protobuf message found in {}", path.display())),
+    }
+}
+
+/// Return the message indexes used by the Confluent protobuf framing: the index of the
+/// top-level message in the file, followed by the indexes of the nested messages down to it.
+fn proto_message_indexes(desc: &MessageDescriptor) -> Vec<i64> {
+    let mut indexes = Vec::new();
+    let mut current = desc.clone();
+
+    while let Some(parent) = current.enclosing_message() {
+        let i = parent.nested_messages().position(|m| m.full_name() == current.full_name()).unwrap_or(0);
+        indexes.push(i as i64);
+
+        current = parent;
+    }
+
+    let file = current.file_descriptor();
+    let i = file.messages().position(|m| m.full_name() == current.full_name()).unwrap_or(0);
+    indexes.push(i as i64);
+
+    indexes.reverse();
+    indexes
+}
+
+fn write_varint(buf: &mut Vec<u8>, value: i64) {
+    // Zigzag encoding as used by the Confluent serializers
+    let mut v = ((value << 1) ^ (value >> 63)) as u64;
+
+    while v >= 0x80 {
+        buf.push((v as u8) | 0x80);
+        v >>= 7;
+    }
+    buf.push(v as u8);
+}
+
+fn read_varint(bytes: &[u8], pos: &mut usize) -> Result<i64> {
+    let mut v: u64 = 0;
+    let mut shift = 0;
+
+    loop {
+        let b = *bytes.get(*pos).ok_or_else(|| anyhow!("Truncated varint in the message"))?;
+        *pos += 1;
+
+        v |= ((b & 0x7f) as u64) << shift;
+        if b & 0x80 == 0 {
+            break;
+        }
+
+        shift += 7;
+        if shift > 63 {
+            return Err(anyhow!("Invalid varint in the message"));
+        }
+    }
+
+    Ok(((v >> 1) as i64) ^ -((v & 1) as i64))
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RegistrySchema {
+    #[serde(default)]
+    id: Option<u32>,
+    schema: String,
+    #[serde(default)]
+    schema_type: Option<String>,
+}
+
+impl RegistrySchema {
+    /// Format of the schema: the registry omits the type of Avro schemas.
+    fn format(&self) -> Result<ValueFormat> {
+        match self.schema_type.as_deref() {
+            None | Some("AVRO") => Ok(ValueFormat::Avro),
+            Some("PROTOBUF") => Ok(ValueFormat::Protobuf),
+            Some(t) => Err(anyhow!("Unsupported schema type {} in the registry", t)),
+        }
+    }
+}
+
+async fn fetch_registry_schema(url: &str) -> Result<RegistrySchema> {
+    debug!("Fetching schema from the registry: {}", url);
+
+    let schema = reqwest::get(url)
+        .await?
+        .error_for_status()?
+        .json::<RegistrySchema>()
+        .await?;
+
+    Ok(schema)
+}
+
+/// Convert a YAML message to the JSON value to encode.
+///
+/// Strings are parsed as YAML/JSON documents so that block strings can be used
+/// to describe structured messages.
+pub fn message_to_json(message: &yamlValue) -> Result<jsonValue> {
+    match message {
+        yamlValue::String(s) => {
+            let v: yamlValue = serde_yaml::from_str(s)?;
+            Ok(serde_json::to_value(v)?)
+        },
+        v => Ok(serde_json::to_value(v)?),
+    }
+}
+
+pub struct Encoder {
+    format: ValueFormat,
+    schema: Option<Schema>,
+    schema_id: Option<u32>,
+}
+
+impl Encoder {
+    pub async fn new(format: &ValueFormat, config: &SchemaConfig, topic: &str) -> Result<Self> {
+        let mut encoder = Encoder {
+            format: format.clone(),
+            schema: None,
+            schema_id: None,
+        };
+
+        if *format != ValueFormat::Avro && *format != ValueFormat::Protobuf {
+            return Ok(encoder);
+        }
+
+        let message_type = config.message_type.as_deref();
+
+        if let Some(f) = &config.file {
+            encoder.schema = Some(Schema::load(format, f, message_type)?);
+        } else if let Some(url) = &config.registry_url {
+            let subject = config.subject.clone().unwrap_or_else(|| format!("{}-value", topic));
+            let version = config.version.clone().unwrap_or_else(|| "latest".to_string());
+
+            let rs = fetch_registry_schema(&format!("{}/subjects/{}/versions/{}", url.trim_end_matches('/'), subject, version)).await?;
+
+            let registry_format = rs.format()?;
+            if registry_format != *format {
+                return Err(anyhow!("Subject {} holds a {:?} schema, not a {:?} one", subject, registry_format, format));
+            }
+
+            encoder.schema = Some(Schema::parse(format, &rs.schema, message_type)?);
+            encoder.schema_id = rs.id;
+        }
+
+        Ok(encoder)
+    }
+
+    pub fn encode(&self, message: &yamlValue) -> Result<Vec<u8>> {
+        match self.format {
+            ValueFormat::String => match message {
+                yamlValue::String(s) => Ok(s.as_bytes().to_vec()),
+                v => Ok(serde_json::to_vec(&message_to_json(v)?)?),
+            },
+            ValueFormat::Json => Ok(serde_json::to_vec(&message_to_json(message)?)?),
+            ValueFormat::Avro | ValueFormat::Protobuf => {
+                let schema = self.schema.as_ref().ok_or_else(|| anyhow!("No schema loaded for {:?}", self.format))?;
+                let payload = schema.encode(&message_to_json(message)?)?;
+
+                let id = match self.schema_id {
+                    Some(id) => id,
+                    None => return Ok(payload),
+                };
+
+                let mut buf = vec![MAGIC_BYTE];
+                buf.extend_from_slice(&id.to_be_bytes());
+
+                if let Schema::Protobuf(d) = schema {
+                    let indexes = proto_message_indexes(d);
+
+                    if indexes == vec![0] {
+                        buf.push(0);
+                    } else {
+                        write_varint(&mut buf, indexes.len() as i64);
+                        for i in indexes {
+                            write_varint(&mut buf, i);
+                        }
+                    }
+                }
+
+                buf.extend_from_slice(&payload);
+
+                Ok(buf)
+            },
+        }
+    }
+}
+
+pub struct Decoder {
+    format: ValueFormat,
+    message_type: Option<String>,
+    schema: Option<Schema>,
+    registry_url: Option<String>,
+    registry_schemas: HashMap<u32, Schema>,
+}
+
+impl Decoder {
+    pub fn new(format: &ValueFormat, config: &SchemaConfig) -> Result<Self> {
+        let mut decoder = Decoder {
+            format: format.clone(),
+            message_type: config.message_type.clone(),
+            schema: None,
+            registry_url: config.registry_url.clone(),
+            registry_schemas: HashMap::new(),
+        };
+
+        if *format == ValueFormat::Avro || *format == ValueFormat::Protobuf {
+            if let Some(f) = &config.file {
+                decoder.schema = Some(Schema::load(format, f, config.message_type.as_deref())?);
+            }
+        }
+
+        Ok(decoder)
+    }
+
+    pub async fn decode(&mut self, bytes: &[u8]) -> Result<jsonValue> {
+        match self.format {
+            ValueFormat::String => {
+                let payload = std::str::from_utf8(bytes)?;
+
+                match serde_json::from_str(payload) {
+                    Ok(v) => Ok(v),
+                    Err(_e) => Ok(jsonValue::String(payload.to_string())),
+                }
+            },
+            ValueFormat::Json => Ok(serde_json::from_slice(bytes)?),
+            ValueFormat::Avro | ValueFormat::Protobuf => {
+                if let Some(s) = &self.schema {
+                    return s.decode(bytes);
+                }
+
+                self.decode_registry(bytes).await
+            },
+        }
+    }
+
+    async fn decode_registry(&mut self, bytes: &[u8]) -> Result<jsonValue> {
+        if bytes.len() < 5 || bytes[0] != MAGIC_BYTE {
+            return Err(anyhow!("Message is not in the schema registry wire format"));
+        }
+
+        let id = u32::from_be_bytes([bytes[1], bytes[2], bytes[3], bytes[4]]);
+
+        if !self.registry_schemas.contains_key(&id) {
+            let url = self.registry_url.clone().ok_or_else(|| anyhow!("schema.registry_url is not specified"))?;
+            let rs = fetch_registry_schema(&format!("{}/schemas/ids/{}", url.trim_end_matches('/'), id)).await?;
+
+            let schema = Schema::parse(&rs.format()?, &rs.schema, self.message_type.as_deref())?;
+            self.registry_schemas.insert(id, schema);
+        }
+
+        let mut schema = self.registry_schemas[&id].clone();
+        let mut pos = 5;
+
+        if let Schema::Protobuf(d) = &schema {
+            let count = read_varint(bytes, &mut pos)?;
+            let mut indexes = Vec::new();
+
+            for _ in 0..count {
+                indexes.push(read_varint(bytes, &mut pos)?);
+            }
+
+            if indexes.is_empty() {
+                indexes.push(0);
+            }
+
+            let mut desc = d.file_descriptor().messages().nth(indexes[0] as usize);
+            for i in indexes.iter().skip(1) {
+                desc = desc.and_then(|m| m.nested_messages().nth(*i as usize));
+            }
+
+            schema = Schema::Protobuf(desc.ok_or_else(|| anyhow!("Unknown protobuf message indexes: {:?}", indexes))?);
+        }
+
+        schema.decode(&bytes[pos..])
+    }
+}
+
+/// Decode payloads from synchronous code such as verifiers.
+///
+/// Fetching schemas from a registry is asynchronous, so the decoding runs in
+/// its own runtime on a separate thread, whether or not the caller already
+/// runs inside a tokio runtime.
+pub fn decode_blocking(format: &ValueFormat, config: &SchemaConfig, payloads: Vec<Option<Vec<u8>>>) -> Result<Vec<jsonValue>> {
+    let mut decoder = Decoder::new(format, config)?;
+
+    let handle = std::thread::spawn(move || -> Result<Vec<jsonValue>> {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()?;
+
+        rt.block_on(async move {
+            let mut values = Vec::new();
+
+            for p in payloads.iter() {
+                match p {
+                    Some(bytes) => values.push(decoder.decode(bytes).await?),
+                    None => values.push(jsonValue::Null),
+                }
+            }
+
+            Ok(values)
+        })
+    });
+
+    handle.join().map_err(|_| anyhow!("Decoding thread panicked"))?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn codec_varint_roundtrip() {
+        let mut buf = Vec::new();
+        for v in [0, 1, 2, 63, 64, 300, -1, -300] {
+            write_varint(&mut buf, v);
+        }
+
+        let mut pos = 0;
+        for v in [0, 1, 2, 63, 64, 300, -1, -300] {
+            assert_eq!(v, read_varint(&buf, &mut pos).unwrap());
+        }
+    }
+
+    #[tokio::test]
+    async fn codec_avro_roundtrip() {
+        let file = std::env::temp_dir().join("test-faker-codec.avsc");
+        fs::write(&file, r#"
+{
+  "type": "record",
+  "name": "Order",
+  "fields": [
+    { "name": "id", "type": "string" },
+    { "name": "quantity", "type": "int" }
+  ]
+}
+"#).unwrap();
+
+        let config = SchemaConfig {
+            file: Some(file.to_str().unwrap().to_string()),
+            ..Default::default()
+        };
+
+        let encoder = Encoder::new(&ValueFormat::Avro, &config, "orders").await.unwrap();
+        let mut decoder = Decoder::new(&ValueFormat::Avro, &config).unwrap();
+
+        let message: yamlValue = serde_yaml::from_str("id: order1\nquantity: 3").unwrap();
+        let bytes = encoder.encode(&message).unwrap();
+
+        assert_eq!(json!({"id": "order1", "quantity": 3}), decoder.decode(&bytes).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn codec_protobuf_roundtrip() {
+        let dir = std::env::temp_dir().join("test-faker-codec");
+        fs::create_dir_all(&dir).unwrap();
+
+        let file = dir.join("order.proto");
+        fs::write(&file, r#"
+syntax = "proto3";
+package shop;
+
+message Order {
+  string id = 1;
+  int32 quantity = 2;
+}
+"#).unwrap();
+
+        let config = SchemaConfig {
+            file: Some(file.to_str().unwrap().to_string()),
+            message_type: Some("shop.Order".to_string()),
+            ..Default::default()
+        };
+
+        let encoder = Encoder::new(&ValueFormat::Protobuf, &config, "orders").await.unwrap();
+        let mut decoder = Decoder::new(&ValueFormat::Protobuf, &config).unwrap();
+
+        let message = yamlValue::String(r#"{"id": "order1", "quantity": 3}"#.to_string());
+        let bytes = encoder.encode(&message).unwrap();
+
+        assert_eq!(json!({"id": "order1", "quantity": 3}), decoder.decode(&bytes).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn codec_protobuf_nested_indexes() {
+        let dir = std::env::temp_dir().join("test-faker-codec-nested");
+        fs::create_dir_all(&dir).unwrap();
+
+        let file = dir.join("shop.proto");
+        fs::write(&file, r#"
+syntax = "proto3";
+package shop;
+
+message Order {
+  message Line {
+    string sku = 1;
+  }
+  message Delivery {
+    message Address {
+      string city = 1;
+    }
+  }
+}
+
+message Refund {
+  string id = 1;
+}
+"#).unwrap();
+
+        let path = file.to_str().unwrap();
+        let message = |t: &str| match Schema::load(&ValueFormat::Protobuf, path, Some(t)).unwrap() {
+            Schema::Protobuf(d) => d,
+            _ => unreachable!(),
+        };
+
+        assert_eq!(vec![0], proto_message_indexes(&message("shop.Order")));
+        assert_eq!(vec![1], proto_message_indexes(&message("shop.Refund")));
+        assert_eq!(vec![0, 1, 0], proto_message_indexes(&message("shop.Order.Delivery.Address")));
+
+        // Registry framing: the decoder walks the indexes from the top-level messages of the file
+        let config = SchemaConfig {
+            file: Some(path.to_string()),
+            message_type: Some("shop.Order.Delivery.Address".to_string()),
+            ..Default::default()
+        };
+
+        let mut encoder = Encoder::new(&ValueFormat::Protobuf, &config, "orders").await.unwrap();
+        encoder.schema_id = Some(7);
+
+        let bytes = encoder.encode(&yamlValue::String(r#"{"city": "Lyon"}"#.to_string())).unwrap();
+        assert_eq!(&[MAGIC_BYTE, 0, 0, 0, 7, 6, 0, 2, 0], &bytes[..9]);
+
+        let mut decoder = Decoder::new(&ValueFormat::Protobuf, &SchemaConfig::default()).unwrap();
+        decoder.registry_schemas.insert(7, Schema::load(&ValueFormat::Protobuf, path, Some("shop.Order")).unwrap());
+
+        assert_eq!(json!({"city": "Lyon"}), decoder.decode(&bytes).await.unwrap());
+    }
+}
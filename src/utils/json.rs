@@ -9,3 +9,44 @@ macro_rules! json_map {
         }
     }
 }
+
+/// Return a unified diff between the pretty-printed expected and actual values,
+/// or None if they are equal.
+pub fn json_diff(expected: &serde_json::Value, actual: &serde_json::Value) -> Option<String> {
+    if expected == actual {
+        return None;
+    }
+
+    let expected_text = serde_json::to_string_pretty(expected).unwrap_or_default();
+    let actual_text = serde_json::to_string_pretty(actual).unwrap_or_default();
+
+    let diff = similar::TextDiff::from_lines(&expected_text, &actual_text)
+        .unified_diff()
+        .header("expected", "actual")
+        .to_string();
+
+    Some(diff)
+}
+
+/// Return the value corresponding to a dot-separated path (e.g. `order.items.0.id`),
+/// array indexes being also accepted between brackets (e.g. `$.order.items[0].id`).
+///
+/// If no value is found, it will return None.
+pub fn json_get_value_by_path<'a>(value: &'a serde_json::Value, path: &str) -> Option<&'a serde_json::Value> {
+    // `$` is the root only when followed by a segment: `$ref` is a key
+    let path = path.strip_prefix('$')
+        .filter(|p| p.is_empty() || p.starts_with('.') || p.starts_with('['))
+        .unwrap_or(path);
+    let path = path.replace('[', ".").replace(']', "");
+    let path = path.trim_start_matches('.');
+
+    if path.is_empty() {
+        return Some(value);
+    }
+
+    path.split('.').try_fold(value, |v, k| match v {
+        serde_json::Value::Array(a) => k.parse::<usize>().ok().and_then(|i| a.get(i)),
+        serde_json::Value::Object(o) => o.get(k),
+        _ => None,
+    })
+}
@@ -1,19 +1,40 @@
+use std::collections::HashMap;
+use std::time::Instant;
+
 use tokio::time::{sleep, Duration};
 
+use serde_yaml::Mapping;
+use serde_json::{Map, Value as jsonValue};
+
+use anyhow::{anyhow, Result};
+
 use rdkafka::{
-    //consumer::{BaseConsumer, DefaultConsumerContext},
+    consumer::{BaseConsumer, Consumer},
     client::DefaultClientContext,
     admin::{
         AdminClient, AdminOptions, NewTopic,
         TopicReplication,
     },
     //metadata::Metadata,
+    message::{Headers, Message},
     ClientConfig,
+    Offset,
+    TopicPartitionList,
     //producer::FutureProducer,
 };
 
 use log::*;
 
+/// A message read from a topic by a verifier.
+#[derive(Default, Debug, Clone)]
+pub struct TopicMessage {
+    pub partition: i32,
+    pub offset: i64,
+    pub key: Option<String>,
+    pub headers: Map<String, jsonValue>,
+    pub payload: Option<Vec<u8>>,
+}
+
 
 pub fn create_config(brokers: &str) -> ClientConfig {
     let mut config = ClientConfig::new();
@@ -45,3 +66,206 @@ pub async fn reinit_topics(brokers: &str, topics: &[&str]) {
         sleep(Duration::from_secs(3)).await;
     }
 }
+
+/// Lag of a consumer group on a topic partition.
+#[derive(Default, Debug, Clone, PartialEq)]
+pub struct PartitionLag {
+    pub partition: i32,
+    /// None if the group has not committed any offset on the partition
+    pub committed: Option<i64>,
+    pub low: i64,
+    pub high: i64,
+    pub lag: i64,
+}
+
+/// Compute the lag of a partition from its committed offset and watermarks.
+///
+/// Without committed offset, every message still in the partition is lagging.
+pub fn compute_lag(committed: Offset, low: i64, high: i64) -> (Option<i64>, i64) {
+    match committed {
+        Offset::Offset(o) => (Some(o), (high - o).max(0)),
+        _ => (None, high - low),
+    }
+}
+
+/// Return the committed offsets of a consumer group on a topic, compared to the
+/// high watermarks of each partition.
+pub fn fetch_consumer_lag(brokers: &str, group_id: &str, topic: &str, timeout: Duration) -> Result<Vec<PartitionLag>> {
+    let mut config = create_config(brokers);
+    config.set("group.id", group_id)
+        .set("enable.auto.commit", "false");
+
+    let consumer: BaseConsumer = config.create()?;
+
+    let partitions = fetch_partitions(&consumer, topic, timeout)?;
+
+    let mut tpl = TopicPartitionList::new();
+    for p in partitions.iter() {
+        tpl.add_partition(topic, *p);
+    }
+
+    let committed = consumer.committed_offsets(tpl, timeout)?;
+
+    let mut result = Vec::new();
+    for p in partitions.iter() {
+        let (low, high) = consumer.fetch_watermarks(topic, *p, timeout)?;
+        let offset = committed.find_partition(topic, *p)
+            .map(|e| e.offset())
+            .unwrap_or(Offset::Invalid);
+
+        let (committed_offset, lag) = compute_lag(offset, low, high);
+
+        result.push(PartitionLag {
+            partition: *p,
+            committed: committed_offset,
+            low,
+            high,
+            lag,
+        });
+    }
+
+    Ok(result)
+}
+
+/// Return the partition ids of a topic.
+pub fn fetch_partitions(consumer: &BaseConsumer, topic: &str, timeout: Duration) -> Result<Vec<i32>> {
+    let metadata = consumer.fetch_metadata(Some(topic), timeout)?;
+
+    let partitions: Vec<i32> = metadata.topics()
+        .iter()
+        .filter(|t| t.name() == topic)
+        .flat_map(|t| t.partitions().iter().map(|p| p.id()))
+        .collect();
+
+    if partitions.is_empty() {
+        return Err(anyhow!("Topic {} not found", topic));
+    }
+
+    Ok(partitions)
+}
+
+/// Offsets to read in a partition, from `start` (included) to `end` (excluded), bounded by
+/// the watermarks. None if there is nothing to read.
+fn offset_range(low: i64, high: i64, start: Option<i64>, end: Option<i64>) -> Option<(i64, i64)> {
+    let from = start.map_or(low, |s| s.max(low));
+    let to = end.map_or(high, |e| e.min(high));
+
+    (to > from).then_some((from, to))
+}
+
+/// Read the messages of a topic, from the low to the high watermark of each partition.
+///
+/// The high watermarks are taken when the function is called so that the
+/// reading stops once the current content of the topic is consumed.
+/// `start_offset` (included) & `end_offset` (excluded) narrow the range read in every partition.
+pub fn read_topic(brokers: &str, topic: &str, options: &Mapping, start_offset: Option<i64>, end_offset: Option<i64>, timeout: Duration) -> Result<Vec<TopicMessage>> {
+    let mut config = create_config(brokers);
+    config.set("group.id", "test-faker-verifier")
+        .set("enable.auto.commit", "false")
+        .set("enable.partition.eof", "false");
+
+    for (k, v) in options.iter() {
+        if let (Some(k), Some(v)) = (k.as_str(), v.as_str()) {
+            config.set(k, v);
+        }
+    }
+
+    let consumer: BaseConsumer = config.create()?;
+
+    let mut tpl = TopicPartitionList::new();
+    let mut ends = HashMap::new();
+
+    for p in fetch_partitions(&consumer, topic, timeout)? {
+        let (low, high) = consumer.fetch_watermarks(topic, p, timeout)?;
+
+        if let Some((from, to)) = offset_range(low, high, start_offset, end_offset) {
+            tpl.add_partition_offset(topic, p, Offset::Offset(from))?;
+            ends.insert(p, to);
+        }
+    }
+
+    let mut messages = Vec::new();
+
+    if ends.is_empty() {
+        return Ok(messages);
+    }
+
+    consumer.assign(&tpl)?;
+
+    let deadline = Instant::now() + timeout;
+
+    while !ends.is_empty() {
+        if Instant::now() > deadline {
+            return Err(anyhow!("Timeout while reading the topic {}: partitions not fully read: {:?}", topic, ends));
+        }
+
+        match consumer.poll(Duration::from_millis(100)) {
+            None => {
+                // Control records (transactions) are never delivered, so the
+                // position may reach the high watermark without any message.
+                let position = consumer.position()?;
+                ends.retain(|p, high| match position.find_partition(topic, *p).map(|e| e.offset()) {
+                    Some(Offset::Offset(o)) => o < *high,
+                    _ => true,
+                });
+            },
+            Some(Err(e)) => return Err(anyhow!(e)),
+            Some(Ok(m)) => {
+                // Messages past the end offset of a partition already read
+                if ends.get(&m.partition()).is_none_or(|high| m.offset() >= *high) {
+                    continue;
+                }
+
+                let mut headers = Map::new();
+                if let Some(hs) = m.headers() {
+                    for i in 0..hs.count() {
+                        if let Some((name, value)) = hs.get(i) {
+                            headers.insert(name.to_string(), jsonValue::String(String::from_utf8_lossy(value).to_string()));
+                        }
+                    }
+                }
+
+                messages.push(TopicMessage {
+                    partition: m.partition(),
+                    offset: m.offset(),
+                    key: m.key().map(|k| String::from_utf8_lossy(k).to_string()),
+                    headers,
+                    payload: m.payload().map(|p| p.to_vec()),
+                });
+
+                if let Some(high) = ends.get(&m.partition()) {
+                    if m.offset() + 1 >= *high {
+                        ends.remove(&m.partition());
+                    }
+                }
+            },
+        }
+    }
+
+    messages.sort_by_key(|m| (m.partition, m.offset));
+
+    debug!("{} messages read from the topic {}", messages.len(), topic);
+
+    Ok(messages)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn kafka_compute_lag() {
+        assert_eq!((Some(10), 0), compute_lag(Offset::Offset(10), 0, 10));
+        assert_eq!((Some(4), 6), compute_lag(Offset::Offset(4), 0, 10));
+        assert_eq!((None, 7), compute_lag(Offset::Invalid, 3, 10));
+    }
+
+    #[test]
+    fn kafka_offset_range() {
+        assert_eq!(Some((0, 10)), offset_range(0, 10, None, None));
+        assert_eq!(Some((4, 8)), offset_range(0, 10, Some(4), Some(8)));
+        assert_eq!(Some((3, 10)), offset_range(3, 10, Some(1), Some(20)));
+        assert_eq!(None, offset_range(0, 10, Some(10), None));
+        assert_eq!(None, offset_range(0, 0, None, None));
+    }
+}
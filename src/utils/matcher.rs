@@ -0,0 +1,355 @@
+use std::collections::BTreeMap;
+use std::fmt;
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde_json::{Map, Value as jsonValue};
+
+use regex::Regex;
+
+use anyhow::{anyhow, Result};
+
+use log::*;
+
+use crate::utils::json::json_get_value_by_path;
+
+/// Regex compiled once, when the configuration is loaded, and (de)serialized as its pattern.
+#[derive(Debug, Clone)]
+pub struct Pattern(Regex);
+
+impl Pattern {
+    pub fn new(re: &str) -> Result<Self> {
+        Regex::new(re)
+            .map(Pattern)
+            .map_err(|e| anyhow!("Invalid regex {}: {}", re, e))
+    }
+
+    pub fn is_match(&self, text: &str) -> bool {
+        self.0.is_match(text)
+    }
+}
+
+impl fmt::Display for Pattern {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.0.as_str())
+    }
+}
+
+impl PartialEq for Pattern {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.as_str() == other.0.as_str()
+    }
+}
+
+impl Serialize for Pattern {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.0.as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for Pattern {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let re = String::deserialize(deserializer)?;
+
+        Pattern::new(&re).map_err(serde::de::Error::custom)
+    }
+}
+
+/// Matcher applied to a string value such as a key, a header or a query parameter.
+#[derive(Debug ,Serialize, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Matcher {
+    Equals(String),
+    Regex(Pattern),
+    Present(bool),
+    Absent(bool),
+}
+
+impl Matcher {
+    pub fn matches(&self, value: Option<&str>) -> bool {
+        match self {
+            Matcher::Equals(expected) => value == Some(expected.as_str()),
+            Matcher::Regex(re) => value.is_some_and(|v| re.is_match(v)),
+            Matcher::Present(p) => value.is_some() == *p,
+            Matcher::Absent(a) => value.is_none() == *a,
+        }
+    }
+}
+
+/// Matcher applied to a payload.
+#[derive(Debug ,Serialize, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum BodyMatcher {
+    /// The payload must be strictly equal.
+    Equals(jsonValue),
+    /// The payload must contain the given subset.
+    Contains(jsonValue),
+    /// The values selected by the paths (`$.a.b[0]` or `a.b.0`) must match.
+    JsonPath(BTreeMap<String, Matcher>),
+    /// The raw payload must match the regex.
+    Regex(Pattern),
+    /// The fields of a form-urlencoded payload must match.
+    Form(BTreeMap<String, Matcher>),
+    /// Any payload matches.
+    Any(bool),
+}
+
+/// Text of a JSON value, strings being taken without their quotes.
+fn json_text(value: &jsonValue) -> String {
+    match value {
+        jsonValue::String(s) => s.clone(),
+        v => v.to_string(),
+    }
+}
+
+impl BodyMatcher {
+    pub fn matches(&self, value: &jsonValue) -> bool {
+        match self {
+            BodyMatcher::Equals(expected) => value == expected,
+            BodyMatcher::Contains(expected) => json_contains(value, expected),
+            BodyMatcher::JsonPath(paths) => paths.iter()
+                .all(|(p, m)| m.matches(json_get_value_by_path(value, p).map(json_text).as_deref())),
+            BodyMatcher::Regex(_) | BodyMatcher::Form(_) => self.matches_text(&json_text(value)),
+            BodyMatcher::Any(a) => *a,
+        }
+    }
+
+    /// Match a raw payload: JSON matchers never match a payload which is not JSON.
+    pub fn matches_raw(&self, raw: &[u8]) -> bool {
+        match self {
+            BodyMatcher::Regex(_) | BodyMatcher::Form(_) => self.matches_text(&String::from_utf8_lossy(raw)),
+            BodyMatcher::Any(a) => *a,
+            _ => serde_json::from_slice::<jsonValue>(raw).is_ok_and(|v| self.matches(&v)),
+        }
+    }
+
+    fn matches_text(&self, text: &str) -> bool {
+        match self {
+            BodyMatcher::Regex(re) => re.is_match(text),
+            BodyMatcher::Form(fields) => match serde_urlencoded::from_str::<Vec<(String, String)>>(text) {
+                Ok(pairs) => fields.iter()
+                    .all(|(name, m)| m.matches(pairs.iter().find(|(k, _)| k == name).map(|(_, v)| v.as_str()))),
+                Err(e) => {
+                    debug!("Invalid form-urlencoded payload: {}", e);
+                    false
+                },
+            },
+            _ => false,
+        }
+    }
+
+    /// Explain why a raw payload does not match, None if it matches.
+    pub fn explain(&self, raw: &[u8]) -> Option<String> {
+        if self.matches_raw(raw) {
+            return None;
+        }
+
+        let value = match self {
+            BodyMatcher::Regex(re) => return Some(format!("did not match /{}/", re)),
+            BodyMatcher::Any(_) => return Some("never matches".to_string()),
+            BodyMatcher::Form(fields) => {
+                let pairs = serde_urlencoded::from_str::<Vec<(String, String)>>(&String::from_utf8_lossy(raw)).unwrap_or_default();
+                let field = fields.iter()
+                    .find(|(name, m)| !m.matches(pairs.iter().find(|(k, _)| k == *name).map(|(_, v)| v.as_str())))
+                    .map(|(name, _)| name.clone())
+                    .unwrap_or_default();
+
+                return Some(format!("differed at field `{}`", field));
+            },
+            _ => match serde_json::from_slice::<jsonValue>(raw) {
+                Ok(v) => v,
+                Err(_) => return Some("is not JSON".to_string()),
+            },
+        };
+
+        let at = match self {
+            BodyMatcher::Equals(expected) => first_difference(&value, expected, false),
+            BodyMatcher::Contains(expected) => first_difference(&value, expected, true),
+            BodyMatcher::JsonPath(paths) => paths.iter()
+                .find(|(p, m)| !m.matches(json_get_value_by_path(&value, p).map(json_text).as_deref()))
+                .map(|(p, _)| p.clone()),
+            _ => None,
+        };
+
+        Some(format!("differed at `{}`", at.unwrap_or_else(|| "$".to_string())))
+    }
+
+    /// Return the value the matcher expects, used to display diffs.
+    pub fn expected(&self) -> jsonValue {
+        match self {
+            BodyMatcher::Equals(v) => v.clone(),
+            BodyMatcher::Contains(v) => v.clone(),
+            m => serde_json::to_value(m).unwrap_or(jsonValue::Null),
+        }
+    }
+}
+
+/// Matcher applied to a Kafka message: key, headers and payload.
+#[derive(Default, Debug ,Serialize, Deserialize, Clone, PartialEq)]
+pub struct MessageMatcher {
+    #[serde(default)]
+    pub key: Option<Matcher>,
+    #[serde(default)]
+    pub headers: BTreeMap<String, Matcher>,
+    #[serde(default)]
+    pub body: Option<BodyMatcher>,
+}
+
+impl MessageMatcher {
+    pub fn matches(&self, key: Option<&str>, headers: &Map<String, jsonValue>, body: &jsonValue) -> bool {
+        if let Some(m) = &self.key {
+            if !m.matches(key) {
+                return false;
+            }
+        }
+
+        for (name, m) in self.headers.iter() {
+            if !m.matches(headers.get(name).and_then(|v| v.as_str())) {
+                return false;
+            }
+        }
+
+        if let Some(m) = &self.body {
+            if !m.matches(body) {
+                return false;
+            }
+        }
+
+        true
+    }
+}
+
+/// Check if `actual` contains `expected`.
+///
+/// Objects must contain every expected key, arrays must contain every
+/// expected element (in any order) and scalars must be equal.
+pub fn json_contains(actual: &jsonValue, expected: &jsonValue) -> bool {
+    match (actual, expected) {
+        (jsonValue::Object(a), jsonValue::Object(e)) => e.iter()
+            .all(|(k, v)| a.get(k).is_some_and(|av| json_contains(av, v))),
+        (jsonValue::Array(a), jsonValue::Array(e)) => e.iter()
+            .all(|v| a.iter().any(|av| json_contains(av, v))),
+        (a, e) => a == e,
+    }
+}
+
+/// Path of the first difference between `actual` and `expected` (`$.items[1].sku`), None if they match.
+///
+/// With `subset`, `actual` only has to contain `expected`, as for `json_contains`.
+pub fn first_difference(actual: &jsonValue, expected: &jsonValue, subset: bool) -> Option<String> {
+    diff_at(actual, expected, subset, "$")
+}
+
+fn diff_at(actual: &jsonValue, expected: &jsonValue, subset: bool, at: &str) -> Option<String> {
+    match (actual, expected) {
+        (jsonValue::Object(a), jsonValue::Object(e)) => {
+            for (k, v) in e.iter() {
+                let path = format!("{}.{}", at, k);
+
+                match a.get(k) {
+                    Some(av) => {
+                        if let Some(d) = diff_at(av, v, subset, &path) {
+                            return Some(d);
+                        }
+                    },
+                    None => return Some(path),
+                }
+            }
+
+            a.keys()
+                .find(|k| !subset && !e.contains_key(*k))
+                .map(|k| format!("{}.{}", at, k))
+        },
+        (jsonValue::Array(a), jsonValue::Array(e)) if subset => e.iter()
+            .position(|v| !a.iter().any(|av| json_contains(av, v)))
+            .map(|i| format!("{}[{}]", at, i)),
+        (jsonValue::Array(a), jsonValue::Array(e)) => {
+            for (i, (av, v)) in a.iter().zip(e.iter()).enumerate() {
+                if let Some(d) = diff_at(av, v, subset, &format!("{}[{}]", at, i)) {
+                    return Some(d);
+                }
+            }
+
+            (a.len() != e.len()).then(|| format!("{}[{}]", at, a.len().min(e.len())))
+        },
+        (a, e) if a == e => None,
+        _ => Some(at.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn matcher_matches() {
+        assert!(Matcher::Equals("a".to_string()).matches(Some("a")));
+        assert!(!Matcher::Equals("a".to_string()).matches(None));
+        let bearer = Matcher::Regex(Pattern::new("^Bearer .+$").unwrap());
+        assert!(bearer.matches(Some("Bearer token")));
+        assert!(!bearer.matches(Some("Basic xxx")));
+        assert!(!bearer.matches(None));
+        assert!(Matcher::Present(true).matches(Some("")));
+        assert!(Matcher::Absent(true).matches(None));
+        assert!(!Matcher::Absent(true).matches(Some("a")));
+    }
+
+    #[test]
+    fn matcher_json_contains() {
+        let actual = json!({"id": 1, "items": [{"sku": "a", "qty": 1}, {"sku": "b", "qty": 2}], "status": "new"});
+
+        assert!(json_contains(&actual, &json!({"status": "new"})));
+        assert!(json_contains(&actual, &json!({"items": [{"sku": "b"}]})));
+        assert!(!json_contains(&actual, &json!({"items": [{"sku": "c"}]})));
+        assert!(!json_contains(&actual, &json!({"missing": null})));
+    }
+
+    #[test]
+    fn matcher_body() {
+        let body = json!({"user": {"id": 42, "name": "bob"}, "items": ["a", "b"]});
+
+        let m: BodyMatcher = serde_yaml::from_str("json_path: {\"$.user.id\": {equals: \"42\"}, \"items.1\": {equals: b}}").unwrap();
+        assert!(m.matches(&body));
+        assert!(m.matches_raw(body.to_string().as_bytes()));
+        assert!(!m.matches_raw(b"not json"));
+
+        let m: BodyMatcher = serde_yaml::from_str("json_path: {\"$.items[1]\": {equals: b}, \"$.user\": {regex: bob}}").unwrap();
+        assert!(m.matches(&body));
+
+        let m = BodyMatcher::Form(BTreeMap::from([
+            ("name".to_string(), Matcher::Equals("bob smith".to_string())),
+            ("token".to_string(), Matcher::Absent(true)),
+        ]));
+        assert!(m.matches_raw(b"name=bob+smith&age=3"));
+        assert!(!m.matches_raw(b"name=bob+smith&token=x"));
+
+        let m: BodyMatcher = serde_yaml::from_str("regex: ^id=\\d+$").unwrap();
+        assert!(m.matches_raw(b"id=12"));
+        assert_eq!(json!({"regex": "^id=\\d+$"}), serde_json::to_value(&m).unwrap());
+        assert!(BodyMatcher::Any(true).matches_raw(b""));
+        assert!(serde_yaml::from_str::<BodyMatcher>("regex: (").is_err());
+    }
+
+    #[test]
+    fn matcher_explain() {
+        let actual = json!({"type": "order", "payload": {"id": 1, "items": ["a", "b"]}});
+
+        assert_eq!(None, first_difference(&actual, &actual, false));
+        assert_eq!(Some("$.payload.id".to_string()), first_difference(&actual, &json!({"type": "order", "payload": {"id": 2, "items": ["a", "b"]}}), false));
+        assert_eq!(Some("$.payload.items[1]".to_string()), first_difference(&actual, &json!({"payload": {"items": ["a", "c"]}}), true));
+        assert_eq!(Some("$.extra".to_string()), first_difference(&json!({"extra": 1}), &json!({}), false));
+        assert_eq!(None, first_difference(&json!({"extra": 1}), &json!({}), true));
+
+        // The reported paths select the values of json_path matchers
+        let path = first_difference(&actual, &json!({"payload": {"items": ["a", "c"]}}), true).unwrap();
+        assert_eq!(Some(&json!("b")), json_get_value_by_path(&actual, &path));
+        assert_eq!(Some(&json!(["a", "b"])), json_get_value_by_path(&json!([["a", "b"]]), "$[0]"));
+        assert_eq!(Some(&json!(1)), json_get_value_by_path(&json!({"$ref": 1}), "$ref"));
+
+        let raw = actual.to_string();
+        let m = BodyMatcher::Equals(json!({"type": "order", "payload": {"id": 1}}));
+        assert_eq!(Some("differed at `$.payload.items`".to_string()), m.explain(raw.as_bytes()));
+        assert_eq!(None, BodyMatcher::Contains(json!({"payload": {"id": 1}})).explain(raw.as_bytes()));
+        assert_eq!(Some("is not JSON".to_string()), m.explain(b"oops"));
+    }
+}
@@ -3,3 +3,8 @@ pub mod yaml;
 #[macro_use]
 pub mod json;
 pub mod kafka;
+pub mod codec;
+pub mod matcher;
+pub mod template;
+pub mod path;
+pub mod unmatched;
@@ -0,0 +1,89 @@
+use serde_json::{Map, Value as jsonValue};
+
+use anyhow::{anyhow, Result};
+
+/// Check a route path: `:name` captures a segment, `*name` captures the rest of the path.
+pub fn validate_pattern(pattern: &str) -> Result<()> {
+    if !pattern.starts_with('/') {
+        return Err(anyhow!("Path {} must start with /", pattern));
+    }
+
+    let segments: Vec<&str> = pattern.split('/').skip(1).collect();
+
+    for (i, s) in segments.iter().enumerate() {
+        if (s.starts_with(':') || s.starts_with('*')) && s.len() == 1 {
+            return Err(anyhow!("Path {}: parameters must be named", pattern));
+        }
+
+        if s.starts_with('*') && i != segments.len() - 1 {
+            return Err(anyhow!("Path {}: a wildcard must be the last segment", pattern));
+        }
+    }
+
+    Ok(())
+}
+
+/// Match a request path against a route path, returning the captured values.
+pub fn match_path(pattern: &str, path: &str) -> Option<Map<String, jsonValue>> {
+    let mut params = Map::new();
+
+    let mut segments = path.split('/').skip(1);
+
+    for p in pattern.split('/').skip(1) {
+        if let Some(name) = p.strip_prefix('*') {
+            let rest: Vec<&str> = segments.by_ref().collect();
+            params.insert(name.to_string(), jsonValue::String(rest.join("/")));
+
+            return Some(params);
+        }
+
+        let s = segments.next()?;
+
+        match p.strip_prefix(':') {
+            Some(name) if !s.is_empty() => { params.insert(name.to_string(), jsonValue::String(s.to_string())); },
+            Some(_) => return None,
+            None if p != s => return None,
+            None => {},
+        }
+    }
+
+    match segments.next() {
+        Some(_) => None,
+        None => Some(params),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn path_match() {
+        assert_eq!(Some(Map::new()), match_path("/users", "/users"));
+        assert_eq!(None, match_path("/users", "/users/1"));
+        assert_eq!(None, match_path("/users/:id", "/users"));
+        assert_eq!(None, match_path("/users/:id", "/users/"));
+
+        assert_eq!(
+            json!({"id": "1", "order": "o2"}),
+            jsonValue::Object(match_path("/users/:id/orders/:order", "/users/1/orders/o2").unwrap())
+        );
+        assert_eq!(
+            json!({"file": "a/b/c.txt"}),
+            jsonValue::Object(match_path("/files/*file", "/files/a/b/c.txt").unwrap())
+        );
+        assert_eq!(
+            json!({"file": ""}),
+            jsonValue::Object(match_path("/files/*file", "/files/").unwrap())
+        );
+    }
+
+    #[test]
+    fn path_validate() {
+        assert!(validate_pattern("/users/:id/*rest").is_ok());
+        assert!(validate_pattern("/files/*rest/meta").is_err());
+        assert!(validate_pattern("/users/:").is_err());
+        assert!(validate_pattern("users").is_err());
+    }
+}
@@ -0,0 +1,168 @@
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::sync::Mutex;
+
+use serde_json::Value as jsonValue;
+
+use anyhow::{anyhow, Result};
+
+use handlebars::{Context, Handlebars, Helper, HelperResult, Output, RenderContext, RenderError};
+use lazy_static::lazy_static;
+
+use chrono::Utc;
+use rand::Rng;
+use uuid::Uuid;
+
+lazy_static! {
+    static ref HANDLEBARS: Handlebars<'static> = {
+        let mut hb = Handlebars::new();
+
+        // Templates produce JSON or plain text, not HTML
+        hb.register_escape_fn(handlebars::no_escape);
+
+        hb.register_helper("uuid", Box::new(uuid_helper));
+        hb.register_helper("now", Box::new(now_helper));
+        hb.register_helper("counter", Box::new(counter_helper));
+        hb.register_helper("random", Box::new(random_helper));
+        hb.register_helper("json", Box::new(json_helper));
+
+        hb
+    };
+
+    /// Counters of the `counter` helper, by scope & name
+    static ref COUNTERS: Mutex<HashMap<(String, String), u64>> = Mutex::new(HashMap::new());
+}
+
+/// Key of the template context naming the scope of the counters, see `scope_counters`.
+const COUNTER_SCOPE: &str = "_counter_scope";
+
+/// `{{uuid}}`: a random UUID v4.
+fn uuid_helper(_: &Helper, _: &Handlebars, _: &Context, _: &mut RenderContext, out: &mut dyn Output) -> HelperResult {
+    out.write(&Uuid::new_v4().to_string())?;
+    Ok(())
+}
+
+/// `{{now}}`: the current time in RFC 3339, `{{now format="millis"}}` in ms since the epoch,
+/// or any other chrono format such as `{{now format="%Y-%m-%d"}}`.
+fn now_helper(h: &Helper, _: &Handlebars, _: &Context, _: &mut RenderContext, out: &mut dyn Output) -> HelperResult {
+    let now = Utc::now();
+
+    let text = match h.hash_get("format").and_then(|v| v.value().as_str()) {
+        None => now.to_rfc3339(),
+        Some("millis") => now.timestamp_millis().to_string(),
+        Some("seconds") => now.timestamp().to_string(),
+        Some(f) => {
+            // An invalid specifier fails the formatting instead of panicking in to_string()
+            let mut text = String::new();
+            write!(text, "{}", now.format(f))
+                .map_err(|_| RenderError::new(format!("now: invalid format {}", f)))?;
+            text
+        },
+    };
+
+    out.write(&text)?;
+    Ok(())
+}
+
+/// `{{counter "name"}}`: 1, 2, 3... on each rendering, per counter name & scope.
+fn counter_helper(h: &Helper, _: &Handlebars, ctx: &Context, _: &mut RenderContext, out: &mut dyn Output) -> HelperResult {
+    let name = h.param(0).and_then(|v| v.value().as_str()).unwrap_or("default");
+    let scope = ctx.data().get(COUNTER_SCOPE).and_then(|v| v.as_str()).unwrap_or("");
+
+    let mut counters = COUNTERS.lock().unwrap();
+    let counter = counters.entry((scope.to_string(), name.to_string())).or_insert(0);
+    *counter += 1;
+
+    out.write(&counter.to_string())?;
+    Ok(())
+}
+
+/// `{{random min=1 max=100}}`: a random integer between min & max (included), 0 to 100 by default.
+fn random_helper(h: &Helper, _: &Handlebars, _: &Context, _: &mut RenderContext, out: &mut dyn Output) -> HelperResult {
+    let min = h.hash_get("min").and_then(|v| v.value().as_i64()).unwrap_or(0);
+    let max = h.hash_get("max").and_then(|v| v.value().as_i64()).unwrap_or(100);
+
+    if min > max {
+        return Err(RenderError::new(format!("random: min {} is greater than max {}", min, max)));
+    }
+
+    out.write(&rand::thread_rng().gen_range(min..=max).to_string())?;
+    Ok(())
+}
+
+/// `{{json body.items}}`: a value serialized in JSON, objects & arrays included.
+fn json_helper(h: &Helper, _: &Handlebars, _: &Context, _: &mut RenderContext, out: &mut dyn Output) -> HelperResult {
+    let value = h.param(0).map(|v| v.value().clone()).unwrap_or(jsonValue::Null);
+
+    out.write(&value.to_string())?;
+    Ok(())
+}
+
+/// Scope the counters of the templates rendered with this context,
+/// so that a faker resets its own counters only.
+pub fn scope_counters(context: &mut jsonValue, scope: &str) {
+    if let Some(c) = context.as_object_mut() {
+        c.insert(COUNTER_SCOPE.to_string(), jsonValue::String(scope.to_string()));
+    }
+}
+
+/// Reset the counters of the `counter` helper in the given scope.
+pub fn reset_counters(scope: &str) {
+    COUNTERS.lock().unwrap().retain(|(s, _), _| s != scope);
+}
+
+/// Render a handlebars template with the given context.
+pub fn render(template: &str, context: &jsonValue) -> Result<String> {
+    HANDLEBARS.render_template(template, context)
+        .map_err(|e| anyhow!("Rendering template: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn template_render() {
+        let context = json!({"request": {"key": "key1", "body": {"id": "order1"}}});
+
+        assert_eq!(
+            r#"{"id": "order1", "key": "key1"}"#,
+            render(r#"{"id": "{{request.body.id}}", "key": "{{request.key}}"}"#, &context).unwrap()
+        );
+    }
+
+    #[test]
+    fn template_helpers() {
+        let context = json!({});
+
+        assert_eq!(36, render("{{uuid}}", &context).unwrap().len());
+        assert!(render(r#"{{now format="millis"}}"#, &context).unwrap().parse::<i64>().is_ok());
+        assert_eq!(10, render(r#"{{now format="%Y-%m-%d"}}"#, &context).unwrap().len());
+        assert!(render(r#"{{now format="%Q"}}"#, &context).is_err());
+
+        let n: i64 = render("{{random min=5 max=7}}", &context).unwrap().parse().unwrap();
+        assert!((5..=7).contains(&n));
+        assert!(render("{{random min=7 max=5}}", &context).is_err());
+
+        assert_eq!(r#"{"a":[1]}"#, render("{{json v}}", &json!({"v": {"a": [1]}})).unwrap());
+
+        assert_eq!("1 2 1", render(r#"{{counter "tpl_a"}} {{counter "tpl_a"}} {{counter "tpl_b"}}"#, &context).unwrap());
+    }
+
+    #[test]
+    fn template_counter_scopes() {
+        let mut first = json!({});
+        scope_counters(&mut first, "scope_a");
+        let mut second = json!({});
+        scope_counters(&mut second, "scope_b");
+
+        assert_eq!("1 2", render(r#"{{counter "n"}} {{counter "n"}}"#, &first).unwrap());
+        assert_eq!("1", render(r#"{{counter "n"}}"#, &second).unwrap());
+
+        // Resetting a scope keeps the counters of the others
+        reset_counters("scope_a");
+        assert_eq!("1", render(r#"{{counter "n"}}"#, &first).unwrap());
+        assert_eq!("2", render(r#"{{counter "n"}}"#, &second).unwrap());
+    }
+}
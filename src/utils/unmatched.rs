@@ -0,0 +1,40 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use lazy_static::lazy_static;
+
+lazy_static! {
+    static ref UNMATCHED: Mutex<HashMap<String, Vec<String>>> = Mutex::new(HashMap::new());
+}
+
+/// Report a request no stub of the faker listening on `server` matched, checked by the scenario at the end of the run.
+pub fn record(server: &str, description: String) {
+    UNMATCHED.lock().unwrap().entry(server.to_string()).or_default().push(description);
+}
+
+/// Requests reported since the last call by the given servers, leaving those of the other scenarios.
+pub fn take(servers: &[String]) -> Vec<(String, Vec<String>)> {
+    let mut unmatched = UNMATCHED.lock().unwrap();
+
+    servers.iter()
+        .filter_map(|s| unmatched.remove(s).map(|r| (s.clone(), r)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unmatched_take() {
+        record("127.0.0.1:4001", "GET /a".to_string());
+        record("127.0.0.1:4002", "GET /b".to_string());
+        record("127.0.0.1:4001", "GET /c".to_string());
+
+        let servers = vec!["127.0.0.1:4001".to_string()];
+        assert_eq!(vec![("127.0.0.1:4001".to_string(), vec!["GET /a".to_string(), "GET /c".to_string()])], take(&servers));
+        assert!(take(&servers).is_empty());
+
+        assert_eq!(1, take(&["127.0.0.1:4002".to_string()]).len());
+    }
+}
@@ -0,0 +1,320 @@
+use serde::{Deserialize, Serialize};
+use serde_yaml::{Value, Mapping};
+use serde_json::Value as jsonValue;
+
+use std::collections::BTreeMap;
+use std::fs;
+
+use anyhow::{anyhow, Result};
+
+use log::*;
+
+use crate::verifier::{Verifier, VerifierMod};
+use crate::utils::matcher::{BodyMatcher, Matcher};
+use crate::utils::path::{match_path, validate_pattern};
+
+// Our plugin implementation
+#[derive(Default, Debug ,Serialize, Deserialize, Clone)]
+struct HttpCalls {
+    /// Journal file of http-server
+    #[serde(default)]
+    journal_file: Option<String>,
+    /// URL serving the journal of a running http-server, as a JSON array or JSON lines
+    #[serde(default)]
+    journal_url: Option<String>,
+    #[serde(default)]
+    calls: Vec<CallMatcher>,
+    /// Names of calls whose first occurrences must happen in this order
+    #[serde(default)]
+    ordering: Vec<Vec<String>>,
+}
+
+#[derive(Default, Debug ,Serialize, Deserialize, Clone)]
+struct CallMatcher {
+    /// Name used by the ordering
+    #[serde(default)]
+    name: Option<String>,
+    /// Any method if not set
+    #[serde(default)]
+    method: Option<String>,
+    /// Path of the request, with `:param` & `*wildcard` segments
+    path: String,
+    #[serde(default)]
+    query: BTreeMap<String, Matcher>,
+    #[serde(default)]
+    headers: BTreeMap<String, Matcher>,
+    #[serde(default)]
+    body: Option<BodyMatcher>,
+    /// At least one call if not set
+    #[serde(default)]
+    count: Option<Count>,
+}
+
+#[derive(Default, Debug ,Serialize, Deserialize, Clone)]
+struct Count {
+    equals: Option<usize>,
+    min: Option<usize>,
+    max: Option<usize>,
+}
+
+/// A request of the journal.
+#[derive(Default, Debug ,Serialize, Deserialize, Clone)]
+struct Call {
+    /// Arrival order, the journal being written in the order of the responses
+    #[serde(default)]
+    sequence: Option<u64>,
+    #[serde(default)]
+    method: String,
+    #[serde(default)]
+    path: String,
+    #[serde(default)]
+    query: BTreeMap<String, String>,
+    #[serde(default)]
+    headers: BTreeMap<String, String>,
+    #[serde(default)]
+    body: jsonValue,
+}
+
+impl CallMatcher {
+    fn description(&self) -> String {
+        let call = format!("{} {}", self.method.as_deref().unwrap_or("ANY"), self.path);
+
+        match &self.name {
+            Some(n) => format!("{} ({})", n, call),
+            None => call,
+        }
+    }
+
+    fn matches(&self, call: &Call) -> bool {
+        self.method.as_ref().is_none_or(|m| m.eq_ignore_ascii_case(&call.method))
+            && match_path(&self.path, &call.path).is_some()
+            && self.query.iter().all(|(k, m)| m.matches(call.query.get(k).map(|v| v.as_str())))
+            && self.headers.iter().all(|(k, m)| m.matches(call.headers.get(&k.to_lowercase()).map(|v| v.as_str())))
+            && self.body.as_ref().is_none_or(|b| b.matches(&call.body))
+    }
+}
+
+/// Parse a journal, either a JSON array or JSON lines, and return the calls in their arrival order.
+fn parse_journal(text: &str) -> Result<Vec<Call>> {
+    let mut calls: Vec<Call> = if text.trim_start().starts_with('[') {
+        serde_json::from_str(text)?
+    } else {
+        text.lines()
+            .filter(|l| !l.trim().is_empty())
+            .map(|l| serde_json::from_str(l).map_err(|e| anyhow!("Invalid journal line {}: {}", l, e)))
+            .collect::<Result<_>>()?
+    };
+
+    // Stable: journals without sequence numbers keep their order
+    calls.sort_by_key(|c| c.sequence);
+
+    Ok(calls)
+}
+
+fn fetch_journal(url: &str) -> Result<String> {
+    let url = url.to_string();
+
+    // Verifiers are synchronous: the request runs on its own runtime
+    let handle = std::thread::spawn(move || -> Result<String> {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()?;
+
+        rt.block_on(async move {
+            let response = reqwest::get(&url).await?.error_for_status()?;
+
+            Ok(response.text().await?)
+        })
+    });
+
+    handle.join().map_err(|_| anyhow!("Journal fetching thread panicked"))?
+}
+
+impl HttpCalls {
+    fn check(&self, journal: &[Call]) -> Vec<String> {
+        let mut failures = Vec::new();
+
+        for c in self.calls.iter() {
+            let n = journal.iter().filter(|call| c.matches(call)).count();
+
+            let ok = match &c.count {
+                Some(count) => count.equals.is_none_or(|e| n == e)
+                    && count.min.is_none_or(|m| n >= m)
+                    && count.max.is_none_or(|m| n <= m),
+                None => n > 0,
+            };
+
+            if !ok {
+                failures.push(format!("{} called {} time(s), expected {:?}", c.description(), n, c.count));
+            }
+        }
+
+        for ordering in self.ordering.iter() {
+            let mut previous: Option<(&String, usize)> = None;
+
+            for name in ordering.iter() {
+                // Names are checked by validate_params
+                let c = match self.calls.iter().find(|c| c.name.as_ref() == Some(name)) {
+                    Some(c) => c,
+                    None => continue,
+                };
+
+                let first = match journal.iter().position(|call| c.matches(call)) {
+                    Some(i) => i,
+                    None => {
+                        failures.push(format!("{} never called, expected in the order {:?}", c.description(), ordering));
+                        break;
+                    },
+                };
+
+                if let Some((p, i)) = previous {
+                    if first < i {
+                        failures.push(format!("{} called before {}, expected in the order {:?}", c.description(), p, ordering));
+                    }
+                }
+
+                previous = Some((name, first));
+            }
+        }
+
+        failures
+    }
+}
+
+impl VerifierMod for HttpCalls {
+    fn validate_params(&self) -> Result<()> {
+        if self.journal_file.is_some() == self.journal_url.is_some() {
+            return Err(anyhow!("Exactly one of journal_file or journal_url must be set"));
+        }
+
+        for c in self.calls.iter() {
+            validate_pattern(&c.path)?;
+        }
+
+        for name in self.ordering.iter().flatten() {
+            if !self.calls.iter().any(|c| c.name.as_ref() == Some(name)) {
+                return Err(anyhow!("No call named {} for the ordering", name));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn func(&self) -> Result<()> {
+        let _ =  env_logger::try_init();
+
+        let text = match (&self.journal_file, &self.journal_url) {
+            (Some(f), _) => fs::read_to_string(f)?,
+            (None, Some(u)) => fetch_journal(u)?,
+            (None, None) => return Err(anyhow!("No journal to verify")),
+        };
+
+        let journal = parse_journal(&text)?;
+        info!("{} requests found in the journal", journal.len());
+
+        let failures = self.check(&journal);
+
+        if !failures.is_empty() {
+            let received: Vec<String> = journal.iter().map(|c| format!("{} {}", c.method, c.path)).collect();
+
+            error!("HTTP calls differ from the expected ones:\n{}", failures.join("\n"));
+
+            return Err(anyhow!("HTTP calls differ from the expected ones:\n{}\nCalls received: {:?}", failures.join("\n"), received));
+        }
+
+        Ok(())
+    }
+}
+
+fn func(params: Mapping) -> Result<()> {
+    let v_params = Value::Mapping(params);
+
+    let verifier: HttpCalls = serde_yaml::from_value(v_params)?;
+
+    verifier.validate_params()?;
+    verifier.func()?;
+
+    Ok(())
+}
+
+inventory::submit!(Verifier {name: "http-calls", func: func });
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn httpcalls_check() {
+        let journal = parse_journal(r#"
+{"method": "POST", "path": "/auth/token", "body": "grant_type=client_credentials"}
+{"method": "GET", "path": "/orders/1", "headers": {"authorization": "Bearer t1"}}
+{"method": "POST", "path": "/orders", "body": {"items": [{"sku": "a"}]}}
+"#).unwrap();
+
+        let verifier: HttpCalls = serde_yaml::from_str(r#"
+journal_file: journal.jsonl
+calls:
+- name: auth
+  method: POST
+  path: /auth/token
+  body:
+    form:
+      grant_type:
+        equals: client_credentials
+  count:
+    equals: 1
+- name: orders
+  path: /orders/:id
+  headers:
+    Authorization:
+      regex: "^Bearer "
+- method: DELETE
+  path: /orders/:id
+  count:
+    equals: 0
+- path: /orders
+  body:
+    contains:
+      items: [{sku: a}]
+ordering:
+- [auth, orders]
+"#).unwrap();
+        verifier.validate_params().unwrap();
+
+        assert!(verifier.check(&journal).is_empty());
+
+        let reversed: HttpCalls = serde_yaml::from_str(r#"
+journal_file: journal.jsonl
+calls:
+- {name: auth, path: /auth/token}
+- {name: orders, path: /orders/:id, count: {min: 3}}
+ordering:
+- [orders, auth]
+"#).unwrap();
+
+        assert_eq!(2, reversed.check(&journal).len());
+    }
+
+    #[test]
+    fn httpcalls_arrival_order() {
+        // The slow token request arrived first but was journaled last
+        let journal = parse_journal(r#"
+{"sequence": 1, "method": "GET", "path": "/orders/1"}
+{"sequence": 2, "method": "GET", "path": "/orders/2"}
+{"sequence": 0, "method": "POST", "path": "/auth/token"}
+"#).unwrap();
+
+        assert_eq!(vec!["/auth/token", "/orders/1", "/orders/2"], journal.iter().map(|c| c.path.as_str()).collect::<Vec<&str>>());
+
+        let verifier: HttpCalls = serde_yaml::from_str(r#"
+journal_file: journal.jsonl
+calls:
+- {name: auth, path: /auth/token}
+- {name: orders, path: /orders/:id}
+ordering:
+- [auth, orders]
+"#).unwrap();
+
+        assert!(verifier.check(&journal).is_empty());
+    }
+}
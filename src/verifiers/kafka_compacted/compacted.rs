@@ -0,0 +1,156 @@
+use serde::{Deserialize, Serialize};
+use serde_yaml::{Value, Mapping};
+use serde_json::{Map, Value as jsonValue};
+
+use std::fs::File;
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+
+use log::*;
+
+use crate::verifier::{Verifier, VerifierMod};
+use crate::utils::codec::{self, SchemaConfig, ValueFormat};
+use crate::utils::json::json_diff;
+use crate::utils::kafka;
+
+// Our plugin implementation
+#[derive(Default, Debug ,Serialize, Deserialize, Clone)]
+struct KafkaCompactedTopic {
+    brokers: Vec<String>,
+    topic: String,
+    #[serde(default)]
+    options: Mapping,
+    #[serde(default)]
+    value_format: ValueFormat,
+    #[serde(default)]
+    schema: SchemaConfig,
+    #[serde(default = "default_timeout_ms")]
+    timeout_ms: u64,
+    /// YAML or JSON file describing the expected key => value map
+    expected: String,
+}
+
+fn default_timeout_ms() -> u64 {
+    10000
+}
+
+/// Build the latest value of each key, tombstones (no payload) deleting the key.
+fn materialize(messages: Vec<(Option<String>, Option<jsonValue>)>) -> Map<String, jsonValue> {
+    let mut state = Map::new();
+
+    for (key, value) in messages.into_iter() {
+        let key = match key {
+            Some(k) => k,
+            None => {
+                warn!("Message without key ignored");
+                continue;
+            },
+        };
+
+        match value {
+            Some(v) => { state.insert(key, v); },
+            None => { state.remove(&key); },
+        }
+    }
+
+    state
+}
+
+impl VerifierMod for KafkaCompactedTopic {
+    fn validate_params(&self) -> Result<()> {
+        if self.brokers.is_empty() {
+            return Err(anyhow!("brokers cannot be empty"));
+        }
+
+        if self.topic.is_empty() {
+            return Err(anyhow!("topic cannot be empty"));
+        }
+
+        if self.expected.is_empty() {
+            return Err(anyhow!("expected cannot be empty"));
+        }
+
+        self.schema.validate(&self.value_format)?;
+
+        Ok(())
+    }
+
+    fn func(&self) -> Result<()> {
+        let _ =  env_logger::try_init();
+
+        // YAML being a superset of JSON, both formats are read the same way
+        let f_expected = File::open(&self.expected)?;
+        let v_expected: Value = serde_yaml::from_reader(f_expected)?;
+        let expected: jsonValue = serde_json::to_value(v_expected)?;
+
+        let raw = kafka::read_topic(&self.brokers.join(","), &self.topic, &self.options, None, None, Duration::from_millis(self.timeout_ms))?;
+
+        // Tombstones have no payload: their decoded value is ignored
+        let payloads = raw.iter().map(|m| m.payload.clone()).collect();
+        let values = codec::decode_blocking(&self.value_format, &self.schema, payloads)?;
+
+        let messages = raw.into_iter()
+            .zip(values)
+            .map(|(m, v)| (m.key, m.payload.map(|_| v)))
+            .collect();
+
+        let actual = jsonValue::Object(materialize(messages));
+
+        if let Some(diff) = json_diff(&expected, &actual) {
+            let mut details = Vec::new();
+
+            if let (Some(e), Some(a)) = (expected.as_object(), actual.as_object()) {
+                for k in e.keys().filter(|k| !a.contains_key(*k)) {
+                    details.push(format!("missing key: {}", k));
+                }
+
+                for k in a.keys().filter(|k| !e.contains_key(*k)) {
+                    details.push(format!("unexpected key: {}", k));
+                }
+
+                for (k, v) in e.iter().filter(|(k, v)| a.get(*k).is_some_and(|av| av != *v)) {
+                    details.push(format!("different value for key {}: expected {}, actual {}", k, v, a[k]));
+                }
+            }
+
+            error!("Latest values of the topic {} differ from {}:\n{}\n{}", self.topic, self.expected, details.join("\n"), diff);
+
+            return Err(anyhow!("Difference found between expected & actual latest values of the topic {}:\n{}\n{}", self.topic, details.join("\n"), diff));
+        }
+
+        Ok(())
+    }
+}
+
+fn func(params: Mapping) -> Result<()> {
+    let v_params = Value::Mapping(params);
+
+    let verifier: KafkaCompactedTopic = serde_yaml::from_value(v_params)?;
+
+    verifier.validate_params()?;
+    verifier.func()?;
+
+    Ok(())
+}
+
+inventory::submit!(Verifier {name: "kafka-compacted-topic", func: func });
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn compacted_materialize() {
+        let messages = vec![
+            (Some("k1".to_string()), Some(json!({"v": 1}))),
+            (Some("k2".to_string()), Some(json!({"v": 1}))),
+            (Some("k1".to_string()), Some(json!({"v": 2}))),
+            (None, Some(json!({"v": 3}))),
+            (Some("k2".to_string()), None),
+        ];
+
+        assert_eq!(json!({"k1": {"v": 2}}), jsonValue::Object(materialize(messages)));
+    }
+}
@@ -0,0 +1,169 @@
+use serde::{Deserialize, Serialize};
+use serde_yaml::{Value, Mapping};
+
+use std::thread::{self, sleep};
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, Result};
+
+use log::*;
+
+use crate::verifier::{Verifier, VerifierMod};
+use crate::utils::kafka::{self, PartitionLag};
+
+// Our plugin implementation
+#[derive(Default, Debug ,Serialize, Deserialize, Clone)]
+struct KafkaConsumerLag {
+    brokers: Vec<String>,
+    group_id: String,
+    topics: Vec<String>,
+    /// Maximum lag accepted on each partition
+    #[serde(default)]
+    max_lag: i64,
+    /// Keep polling until the lag reaches the target or the timeout expires (0 = check once)
+    #[serde(default)]
+    timeout_ms: u64,
+    #[serde(default = "default_poll_interval_ms")]
+    poll_interval_ms: u64,
+}
+
+fn default_poll_interval_ms() -> u64 {
+    1000
+}
+
+impl KafkaConsumerLag {
+    /// Return the partitions whose lag exceeds the target.
+    fn check(&self) -> Result<Vec<(String, PartitionLag)>> {
+        let mut failures = Vec::new();
+
+        for t in self.topics.iter() {
+            let lags = kafka::fetch_consumer_lag(&self.brokers.join(","), &self.group_id, t, Duration::from_secs(5))?;
+
+            for l in lags.into_iter() {
+                debug!("Group {}, topic {}: {:?}", self.group_id, t, l);
+
+                if l.lag > self.max_lag {
+                    failures.push((t.clone(), l));
+                }
+            }
+        }
+
+        Ok(failures)
+    }
+}
+
+/// Call `check` until it returns no failure or the timeout expires, and return its last failures.
+/// It sleeps between the calls: not to be called from an async task.
+fn poll<T>(timeout: Duration, interval: Duration, mut check: impl FnMut() -> Result<Vec<T>>) -> Result<Vec<T>> {
+    let deadline = Instant::now() + timeout;
+
+    loop {
+        let failures = check()?;
+
+        if failures.is_empty() || Instant::now() >= deadline {
+            return Ok(failures);
+        }
+
+        debug!("Still lagging on {} partition(s), polling again...", failures.len());
+        sleep(interval);
+    }
+}
+
+impl VerifierMod for KafkaConsumerLag {
+    fn validate_params(&self) -> Result<()> {
+        if self.brokers.is_empty() {
+            return Err(anyhow!("brokers cannot be empty"));
+        }
+
+        if self.group_id.is_empty() {
+            return Err(anyhow!("group_id cannot be empty"));
+        }
+
+        if self.topics.is_empty() {
+            return Err(anyhow!("topics cannot be empty"));
+        }
+
+        if self.max_lag < 0 {
+            return Err(anyhow!("max_lag cannot be negative"));
+        }
+
+        Ok(())
+    }
+
+    fn func(&self) -> Result<()> {
+        let _ =  env_logger::try_init();
+
+        let timeout = Duration::from_millis(self.timeout_ms);
+        let interval = Duration::from_millis(self.poll_interval_ms);
+
+        // The verifiers are called from the runtime: sleeping on a worker would stall the fakers still running
+        let verifier = self.clone();
+        let handle = thread::spawn(move || poll(timeout, interval, || verifier.check()));
+        let failures = handle.join().map_err(|_| anyhow!("Consumer lag polling thread panicked"))??;
+
+        if failures.is_empty() {
+            info!("Group {} has a lag <= {} on topics {:?}", self.group_id, self.max_lag, self.topics);
+            return Ok(());
+        }
+
+        let details: Vec<String> = failures.iter()
+            .map(|(t, l)| format!(
+                "topic: {}, partition: {}, committed: {}, high watermark: {}, lag: {}",
+                t,
+                l.partition,
+                l.committed.map(|o| o.to_string()).unwrap_or_else(|| "none".to_string()),
+                l.high,
+                l.lag
+            ))
+            .collect();
+
+        Err(anyhow!("Group {} has a lag > {}:\n{}", self.group_id, self.max_lag, details.join("\n")))
+    }
+}
+
+fn func(params: Mapping) -> Result<()> {
+    let v_params = Value::Mapping(params);
+
+    let verifier: KafkaConsumerLag = serde_yaml::from_value(v_params)?;
+
+    verifier.validate_params()?;
+    verifier.func()?;
+
+    Ok(())
+}
+
+inventory::submit!(Verifier {name: "kafka-consumer-lag", func: func });
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lag_poll() {
+        let interval = Duration::from_millis(5);
+
+        // Polls until the lag is caught up
+        let mut calls = 0;
+        let failures = poll(Duration::from_secs(5), interval, || {
+            calls += 1;
+            Ok(if calls < 3 { vec![calls] } else { vec![] })
+        }).unwrap();
+        assert_eq!((3, vec![]), (calls, failures));
+
+        // Checks once without timeout
+        let mut calls = 0;
+        let failures = poll(Duration::ZERO, interval, || { calls += 1; Ok(vec![calls]) }).unwrap();
+        assert_eq!((1, vec![1]), (calls, failures));
+
+        // Returns the last failures once the timeout expires
+        let start = Instant::now();
+        let mut calls = 0;
+        let failures = poll(Duration::from_millis(30), interval, || { calls += 1; Ok(vec![calls]) }).unwrap();
+        assert!(start.elapsed() >= Duration::from_millis(30));
+        assert!(calls > 1);
+        assert_eq!(vec![calls], failures);
+
+        // Errors stop the polling
+        assert!(poll::<i64>(Duration::from_secs(5), interval, || Err(anyhow!("unreachable"))).is_err());
+    }
+}
@@ -0,0 +1,296 @@
+use serde::{Deserialize, Serialize};
+use serde_yaml::{Value, Mapping};
+use serde_json::Value as jsonValue;
+
+use std::collections::HashMap;
+use std::fs::File;
+
+use anyhow::{anyhow, Result};
+
+use log::*;
+
+use crate::verifier::{Verifier, VerifierMod};
+use crate::utils::json::json_get_value_by_path;
+
+// Our plugin implementation
+#[derive(Default, Debug ,Serialize, Deserialize, Clone)]
+struct KafkaLatency {
+    /// Output file of kafka-producer
+    produced: String,
+    /// Output file of kafka-consumer (with metadata: true)
+    consumed: String,
+    /// Only consider the messages produced to & consumed from this topic
+    #[serde(default)]
+    topic: Option<String>,
+    correlation: Correlation,
+    #[serde(default)]
+    thresholds: Thresholds,
+    /// Do not fail when some produced messages were not consumed
+    #[serde(default)]
+    allow_missing: bool,
+}
+
+/// How produced & consumed messages are paired. Only one of them must be set.
+#[derive(Default, Debug ,Serialize, Deserialize, Clone)]
+struct Correlation {
+    /// Header injected by kafka-producer (correlation_header)
+    #[serde(default)]
+    header: Option<String>,
+    /// Pair the messages by key
+    #[serde(default)]
+    key: bool,
+    /// Pair the messages by a value in their payload
+    #[serde(default)]
+    json_path: Option<JsonPathCorrelation>,
+}
+
+#[derive(Default, Debug ,Serialize, Deserialize, Clone)]
+struct JsonPathCorrelation {
+    produced: String,
+    consumed: String,
+}
+
+#[derive(Default, Debug ,Serialize, Deserialize, Clone)]
+struct Thresholds {
+    p50_ms: Option<i64>,
+    p99_ms: Option<i64>,
+    max_ms: Option<i64>,
+}
+
+fn read_records(file: &str) -> Result<Vec<jsonValue>> {
+    let f = File::open(file)?;
+    let v: jsonValue = serde_json::from_reader(f)?;
+
+    match v {
+        jsonValue::Array(a) => Ok(a),
+        _ => Err(anyhow!("{} must contain a JSON array", file)),
+    }
+}
+
+/// Nearest-rank percentile of sorted values.
+fn percentile(sorted: &[i64], p: f64) -> i64 {
+    if sorted.is_empty() {
+        return 0;
+    }
+
+    let rank = ((p / 100.0) * sorted.len() as f64).ceil() as usize;
+
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
+
+impl KafkaLatency {
+    fn correlation_id(&self, record: &jsonValue, path: Option<&str>) -> Option<String> {
+        let c = &self.correlation;
+
+        let v = if let Some(h) = &c.header {
+            record["headers"].get(h)
+        } else if c.key {
+            record.get("key")
+        } else {
+            path.and_then(|p| json_get_value_by_path(&record["message"], p))
+        };
+
+        match v {
+            Some(jsonValue::String(s)) => Some(s.clone()),
+            Some(jsonValue::Null) | None => None,
+            Some(v) => Some(v.to_string()),
+        }
+    }
+
+    fn on_topic(&self, record: &jsonValue) -> bool {
+        match &self.topic {
+            Some(t) => record["topic"].as_str() == Some(t.as_str()),
+            None => true,
+        }
+    }
+
+    /// Pair the produced & consumed messages: latencies in ms, and correlation ids of the messages not consumed.
+    fn pair(&self, produced: &[jsonValue], consumed: &[jsonValue]) -> Result<(Vec<i64>, Vec<String>)> {
+        let produced_path = self.correlation.json_path.as_ref().map(|p| p.produced.as_str());
+        let consumed_path = self.correlation.json_path.as_ref().map(|p| p.consumed.as_str());
+
+        // Correlation id => time at which the message was consumed first
+        let mut received: HashMap<String, i64> = HashMap::new();
+        for r in consumed.iter().filter(|r| self.on_topic(r)) {
+            let received_at = match r["received_at"].as_i64() {
+                Some(t) => t,
+                None => return Err(anyhow!("received_at not found in {}: the consumer must run with metadata: true", self.consumed)),
+            };
+
+            if let Some(id) = self.correlation_id(r, consumed_path) {
+                received.entry(id).or_insert(received_at);
+            }
+        }
+
+        let mut latencies = Vec::new();
+        let mut missing = Vec::new();
+
+        // Messages dropped by the producer's chaos mode were never sent, aborted ones are never read by read_committed consumers
+        for r in produced.iter().filter(|r| self.on_topic(r) && r["dropped"] != jsonValue::Bool(true) && r["aborted"] != jsonValue::Bool(true)) {
+            let id = match self.correlation_id(r, produced_path) {
+                Some(id) => id,
+                None => {
+                    warn!("No correlation id found in the produced message: {}", r);
+                    continue;
+                },
+            };
+
+            let sent_at = r["sent_at"].as_i64()
+                .ok_or_else(|| anyhow!("sent_at not found in {}", self.produced))?;
+
+            match received.get(&id) {
+                Some(t) => latencies.push(t - sent_at),
+                None => missing.push(id),
+            }
+        }
+
+        Ok((latencies, missing))
+    }
+}
+
+impl VerifierMod for KafkaLatency {
+    fn validate_params(&self) -> Result<()> {
+        if self.produced.is_empty() || self.consumed.is_empty() {
+            return Err(anyhow!("produced or consumed cannot be empty"));
+        }
+
+        let c = &self.correlation;
+        let n = [c.header.is_some(), c.key, c.json_path.is_some()].iter().filter(|x| **x).count();
+        if n != 1 {
+            return Err(anyhow!("correlation must specify exactly one of header, key or json_path"));
+        }
+
+        Ok(())
+    }
+
+    fn func(&self) -> Result<()> {
+        let _ =  env_logger::try_init();
+
+        let produced = read_records(&self.produced)?;
+        let consumed = read_records(&self.consumed)?;
+
+        let (mut latencies, missing) = self.pair(&produced, &consumed)?;
+
+        if !missing.is_empty() && !self.allow_missing {
+            return Err(anyhow!("{} produced message(s) not consumed: {:?}", missing.len(), missing));
+        }
+
+        if latencies.is_empty() {
+            return Err(anyhow!("No produced message could be paired with a consumed one"));
+        }
+
+        latencies.sort_unstable();
+
+        let p50 = percentile(&latencies, 50.0);
+        let p99 = percentile(&latencies, 99.0);
+        let max = *latencies.last().unwrap();
+
+        info!("Latencies over {} messages: p50: {}ms, p99: {}ms, max: {}ms", latencies.len(), p50, p99, max);
+
+        let mut failures = Vec::new();
+        for (name, actual, threshold) in [("p50", p50, self.thresholds.p50_ms), ("p99", p99, self.thresholds.p99_ms), ("max", max, self.thresholds.max_ms)] {
+            if let Some(t) = threshold {
+                if actual > t {
+                    failures.push(format!("{} latency {}ms exceeds {}ms", name, actual, t));
+                }
+            }
+        }
+
+        if !failures.is_empty() {
+            return Err(anyhow!("Latency thresholds exceeded: {}", failures.join(", ")));
+        }
+
+        Ok(())
+    }
+}
+
+fn func(params: Mapping) -> Result<()> {
+    let v_params = Value::Mapping(params);
+
+    let verifier: KafkaLatency = serde_yaml::from_value(v_params)?;
+
+    verifier.validate_params()?;
+    verifier.func()?;
+
+    Ok(())
+}
+
+inventory::submit!(Verifier {name: "kafka-latency", func: func });
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn latency_percentile() {
+        let values: Vec<i64> = (1..=100).collect();
+
+        assert_eq!(50, percentile(&values, 50.0));
+        assert_eq!(99, percentile(&values, 99.0));
+        assert_eq!(100, percentile(&values, 100.0));
+        assert_eq!(7, percentile(&[7], 99.0));
+    }
+
+    fn verifier(correlation: &str) -> KafkaLatency {
+        let verifier: KafkaLatency = serde_yaml::from_str(&format!("
+produced: produced.json
+consumed: consumed.json
+topic: orders
+correlation: {}
+", correlation)).unwrap();
+        verifier.validate_params().unwrap();
+
+        verifier
+    }
+
+    #[test]
+    fn latency_pair_by_header() {
+        let produced = json!([
+            {"topic": "orders", "headers": {"correlation-id": "c1"}, "sent_at": 1000},
+            {"topic": "orders", "headers": {"correlation-id": "c2"}, "sent_at": 1000},
+            {"topic": "orders", "headers": {"correlation-id": "c3"}, "sent_at": 1000},
+            // Never sent, nor readable
+            {"topic": "orders", "headers": {"correlation-id": "c4"}, "dropped": true},
+            {"topic": "orders", "headers": {"correlation-id": "c5"}, "sent_at": 1000, "aborted": true},
+            // Filtered out by the topic
+            {"topic": "audit", "headers": {"correlation-id": "c6"}, "sent_at": 1000},
+        ]);
+        let consumed = json!([
+            {"topic": "orders", "headers": {"correlation-id": "c2"}, "received_at": 1030},
+            {"topic": "orders", "headers": {"correlation-id": "c1"}, "received_at": 1010},
+            // A redelivery does not count
+            {"topic": "orders", "headers": {"correlation-id": "c1"}, "received_at": 1500},
+            {"topic": "audit", "headers": {"correlation-id": "c3"}, "received_at": 1020},
+        ]);
+
+        let (latencies, missing) = verifier("{header: correlation-id}")
+            .pair(produced.as_array().unwrap(), consumed.as_array().unwrap()).unwrap();
+
+        assert_eq!(vec![10, 30], latencies);
+        assert_eq!(vec!["c3".to_string()], missing);
+    }
+
+    #[test]
+    fn latency_pair_by_key_or_payload() {
+        let produced = json!([
+            {"topic": "orders", "key": "k1", "message": {"order": {"id": 1}}, "sent_at": 1000},
+            {"topic": "orders", "key": "k2", "message": {"order": {"id": 2}}, "sent_at": 2000},
+        ]);
+        let consumed = json!([
+            {"topic": "orders", "key": "k2", "message": {"reply_to": 2}, "received_at": 2005},
+            {"topic": "orders", "key": "k1", "message": {"reply_to": 1}, "received_at": 1040},
+        ]);
+        let (produced, consumed) = (produced.as_array().unwrap(), consumed.as_array().unwrap());
+
+        let (latencies, missing) = verifier("{key: true}").pair(produced, consumed).unwrap();
+        assert_eq!((vec![40, 5], Vec::<String>::new()), (latencies, missing));
+
+        let (latencies, missing) = verifier("{json_path: {produced: $.order.id, consumed: reply_to}}").pair(produced, consumed).unwrap();
+        assert_eq!((vec![40, 5], Vec::<String>::new()), (latencies, missing));
+
+        // The consumer must record the reception times
+        let consumed = json!([{"topic": "orders", "key": "k1"}]);
+        assert!(verifier("{key: true}").pair(produced, consumed.as_array().unwrap()).is_err());
+    }
+}
@@ -0,0 +1,351 @@
+use serde::{Deserialize, Serialize};
+use serde_yaml::{Value, Mapping};
+use serde_json::Value as jsonValue;
+
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+
+use log::*;
+
+use crate::verifier::{Verifier, VerifierMod};
+use crate::utils::codec::{self, SchemaConfig, ValueFormat};
+use crate::utils::json::json_diff;
+use crate::utils::kafka;
+use crate::utils::matcher::{BodyMatcher, MessageMatcher};
+
+// Our plugin implementation
+#[derive(Default, Debug ,Serialize, Deserialize, Clone)]
+struct KafkaTopic {
+    brokers: Vec<String>,
+    topic: String,
+    #[serde(default)]
+    options: Mapping,
+    #[serde(default)]
+    value_format: ValueFormat,
+    #[serde(default)]
+    schema: SchemaConfig,
+    #[serde(default = "default_timeout_ms")]
+    timeout_ms: u64,
+    /// First offset read in each partition (included), the low watermark by default
+    #[serde(default)]
+    start_offset: Option<i64>,
+    /// Last offset read in each partition (excluded), the high watermark by default
+    #[serde(default)]
+    end_offset: Option<i64>,
+
+    #[serde(default)]
+    count: Option<Count>,
+    /// Expected sequence of messages per key
+    #[serde(default)]
+    ordering: Vec<KeyOrdering>,
+    /// Each matcher must match at least one message
+    #[serde(default)]
+    messages: Vec<MessageMatcher>,
+    /// Every message must match
+    #[serde(default)]
+    every: Option<MessageMatcher>,
+}
+
+fn default_timeout_ms() -> u64 {
+    10000
+}
+
+#[derive(Default, Debug ,Serialize, Deserialize, Clone)]
+struct Count {
+    equals: Option<usize>,
+    min: Option<usize>,
+    max: Option<usize>,
+}
+
+#[derive(Default, Debug ,Serialize, Deserialize, Clone)]
+struct KeyOrdering {
+    key: String,
+    messages: Vec<BodyMatcher>,
+}
+
+struct DecodedMessage {
+    partition: i32,
+    offset: i64,
+    key: Option<String>,
+    headers: serde_json::Map<String, jsonValue>,
+    body: jsonValue,
+}
+
+impl DecodedMessage {
+    /// Explain why the message does not match: the differing key & headers, then the body.
+    fn explain(&self, matcher: &MessageMatcher) -> String {
+        let mut reasons = Vec::new();
+
+        if let Some(k) = &matcher.key {
+            if !k.matches(self.key.as_deref()) {
+                reasons.push(format!("key {:?} does not match {:?}", self.key, k));
+            }
+        }
+
+        for (name, h) in matcher.headers.iter() {
+            let value = self.headers.get(name).and_then(|v| v.as_str());
+            if !h.matches(value) {
+                reasons.push(format!("header {} {:?} does not match {:?}", name, value, h));
+            }
+        }
+
+        if let Some(b) = &matcher.body {
+            let raw = match &self.body {
+                jsonValue::String(s) => s.clone(),
+                v => v.to_string(),
+            };
+
+            if let Some(e) = b.explain(raw.as_bytes()) {
+                reasons.push(format!("body {}", e));
+
+                if let BodyMatcher::Equals(expected) | BodyMatcher::Contains(expected) = b {
+                    reasons.extend(json_diff(expected, &self.body));
+                }
+            }
+        }
+
+        reasons.join("\n")
+    }
+}
+
+impl KafkaTopic {
+    fn check_count(&self, messages: &[DecodedMessage], failures: &mut Vec<String>) {
+        let count = match &self.count {
+            Some(c) => c,
+            None => return,
+        };
+
+        let n = messages.len();
+        let ok = count.equals.is_none_or(|e| n == e)
+            && count.min.is_none_or(|m| n >= m)
+            && count.max.is_none_or(|m| n <= m);
+
+        if !ok {
+            let keys: Vec<&Option<String>> = messages.iter().map(|m| &m.key).collect();
+            failures.push(format!("Message count {} does not satisfy {:?}. Keys received: {:?}", n, count, keys));
+        }
+    }
+
+    fn check_ordering(&self, messages: &[DecodedMessage], failures: &mut Vec<String>) {
+        for o in self.ordering.iter() {
+            let actual: Vec<&DecodedMessage> = messages.iter()
+                .filter(|m| m.key.as_deref() == Some(o.key.as_str()))
+                .collect();
+
+            let ok = actual.len() == o.messages.len()
+                && actual.iter().zip(o.messages.iter()).all(|(a, e)| e.matches(&a.body));
+
+            if !ok {
+                let expected = jsonValue::Array(o.messages.iter().map(|m| m.expected()).collect());
+                let actual = jsonValue::Array(actual.iter().map(|m| m.body.clone()).collect());
+
+                failures.push(format!(
+                    "Messages with the key {} are not in the expected order:\n{}",
+                    o.key,
+                    json_diff(&expected, &actual).unwrap_or_default()
+                ));
+            }
+        }
+    }
+
+    fn check_messages(&self, messages: &[DecodedMessage], failures: &mut Vec<String>) {
+        for (i, e) in self.messages.iter().enumerate() {
+            if messages.iter().any(|m| e.matches(m.key.as_deref(), &m.headers, &m.body)) {
+                continue;
+            }
+
+            // Show the diff against the message with the same key, or the first one
+            let closest = messages.iter()
+                .find(|m| e.key.as_ref().is_some_and(|k| k.matches(m.key.as_deref())))
+                .or_else(|| messages.first());
+
+            let diff = match closest {
+                Some(m) => format!("closest message at partition {}, offset {}: {}", m.partition, m.offset, m.explain(e)),
+                None => "No message in the topic".to_string(),
+            };
+
+            failures.push(format!("No message matches the matcher #{}:\n{}", i, diff));
+        }
+
+        if let Some(e) = &self.every {
+            for m in messages.iter().filter(|m| !e.matches(m.key.as_deref(), &m.headers, &m.body)) {
+                failures.push(format!(
+                    "Message at partition {}, offset {} does not match:\n{}",
+                    m.partition,
+                    m.offset,
+                    m.explain(e)
+                ));
+            }
+        }
+    }
+}
+
+impl VerifierMod for KafkaTopic {
+    fn validate_params(&self) -> Result<()> {
+        if self.brokers.is_empty() {
+            return Err(anyhow!("brokers cannot be empty"));
+        }
+
+        if self.topic.is_empty() {
+            return Err(anyhow!("topic cannot be empty"));
+        }
+
+        self.schema.validate(&self.value_format)?;
+
+        Ok(())
+    }
+
+    fn func(&self) -> Result<()> {
+        let _ =  env_logger::try_init();
+
+        let raw = kafka::read_topic(&self.brokers.join(","), &self.topic, &self.options, self.start_offset, self.end_offset, Duration::from_millis(self.timeout_ms))?;
+        let payloads = raw.iter().map(|m| m.payload.clone()).collect();
+        let bodies = codec::decode_blocking(&self.value_format, &self.schema, payloads)?;
+
+        let messages: Vec<DecodedMessage> = raw.into_iter()
+            .zip(bodies)
+            .map(|(m, body)| DecodedMessage {
+                partition: m.partition,
+                offset: m.offset,
+                key: m.key,
+                headers: m.headers,
+                body,
+            })
+            .collect();
+
+        info!("{} messages read from the topic {}", messages.len(), self.topic);
+
+        let mut failures = Vec::new();
+
+        self.check_count(&messages, &mut failures);
+        self.check_ordering(&messages, &mut failures);
+        self.check_messages(&messages, &mut failures);
+
+        if !failures.is_empty() {
+            for f in failures.iter() {
+                error!("{}", f);
+            }
+
+            return Err(anyhow!("Topic {}: {} assertion(s) failed:\n{}", self.topic, failures.len(), failures.join("\n")));
+        }
+
+        Ok(())
+    }
+}
+
+fn func(params: Mapping) -> Result<()> {
+    let v_params = Value::Mapping(params);
+
+    let verifier: KafkaTopic = serde_yaml::from_value(v_params)?;
+
+    verifier.validate_params()?;
+    verifier.func()?;
+
+    Ok(())
+}
+
+inventory::submit!(Verifier {name: "kafka-topic", func: func });
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn message(offset: i64, key: &str, body: jsonValue) -> DecodedMessage {
+        DecodedMessage {
+            partition: 0,
+            offset,
+            key: Some(key.to_string()),
+            headers: serde_json::Map::new(),
+            body,
+        }
+    }
+
+    fn messages() -> Vec<DecodedMessage> {
+        vec![
+            message(0, "o1", json!({"status": "created"})),
+            message(1, "o2", json!({"status": "created"})),
+            message(2, "o1", json!({"status": "paid", "amount": 10})),
+        ]
+    }
+
+    fn verifier(yaml: &str) -> KafkaTopic {
+        serde_yaml::from_str(&format!("brokers: [localhost:9092]\ntopic: orders\n{}", yaml)).unwrap()
+    }
+
+    #[test]
+    fn topic_check_count() {
+        let mut failures = Vec::new();
+
+        verifier("count: {equals: 3}").check_count(&messages(), &mut failures);
+        verifier("count: {min: 1, max: 3}").check_count(&messages(), &mut failures);
+        assert!(failures.is_empty());
+
+        verifier("count: {min: 4}").check_count(&messages(), &mut failures);
+        assert_eq!(1, failures.len());
+        assert!(failures[0].starts_with("Message count 3"));
+    }
+
+    #[test]
+    fn topic_check_ordering() {
+        let mut failures = Vec::new();
+
+        verifier(r#"
+ordering:
+  - key: o1
+    messages:
+      - equals: {status: created}
+      - contains: {status: paid}
+"#).check_ordering(&messages(), &mut failures);
+        assert!(failures.is_empty());
+
+        verifier(r#"
+ordering:
+  - key: o1
+    messages:
+      - contains: {status: paid}
+      - equals: {status: created}
+"#).check_ordering(&messages(), &mut failures);
+        assert_eq!(1, failures.len());
+        assert!(failures[0].contains("key o1"));
+    }
+
+    #[test]
+    fn topic_check_messages() {
+        let mut failures = Vec::new();
+
+        verifier(r#"
+messages:
+  - key: {equals: o2}
+  - body:
+      contains: {status: paid}
+every:
+  body:
+    json_path: {"$.status": {regex: "^(created|paid)$"}}
+"#).check_messages(&messages(), &mut failures);
+        assert!(failures.is_empty());
+
+        verifier(r#"
+messages:
+  - key: {equals: o1}
+    body:
+      equals: {status: paid, amount: 12}
+every:
+  key: {equals: o1}
+"#).check_messages(&messages(), &mut failures);
+        assert_eq!(2, failures.len());
+
+        // The diff is between the expected & the actual bodies of the closest message
+        assert!(failures[0].contains("closest message at partition 0, offset 0"), "{}", failures[0]);
+        assert!(failures[0].contains("body differed at `$.amount`"), "{}", failures[0]);
+        assert!(!failures[0].contains("\"equals\""), "{}", failures[0]);
+
+        assert!(failures[1].contains("offset 1"), "{}", failures[1]);
+        assert!(failures[1].contains("key Some(\"o2\") does not match"), "{}", failures[1]);
+
+        failures.clear();
+        verifier("messages: [{key: {equals: o1}}]").check_messages(&[], &mut failures);
+        assert!(failures[0].contains("No message in the topic"));
+    }
+}
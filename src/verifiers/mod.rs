@@ -1 +1,6 @@
 pub mod file;
+pub mod kafka_topic;
+pub mod kafka_compacted;
+pub mod kafka_consumer_lag;
+pub mod kafka_latency;
+pub mod http_calls;